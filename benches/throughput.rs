@@ -0,0 +1,158 @@
+use criterion::{BatchSize, BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use glowing_fiesta::ledger::Ledger;
+use glowing_fiesta::ledger_system::LedgerSystem;
+use glowing_fiesta::transaction_generator::{GeneratorConfig, TransactionGenerator};
+use glowing_fiesta::transaction_reader::TransactionReader;
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::env;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// Row counts default to 1M so a plain `cargo bench` finishes in minutes. Set
+// e.g. `BENCH_ROWS=1000000,10000000,100000000` to cover production sized batches.
+const DEFAULT_ROWS: &[u64] = &[1_000_000];
+const DISPUTE_RATES: &[f64] = &[0.0, 0.01, 0.1];
+
+/// Tracks live and peak heap usage so every benchmark can report its memory high-water mark.
+struct PeakAllocator;
+
+static CURRENT: AtomicUsize = AtomicUsize::new(0);
+static PEAK: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for PeakAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = unsafe { System.alloc(layout) };
+        if !ptr.is_null() {
+            let current = CURRENT.fetch_add(layout.size(), Ordering::Relaxed) + layout.size();
+            PEAK.fetch_max(current, Ordering::Relaxed);
+        }
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        unsafe { System.dealloc(ptr, layout) };
+        CURRENT.fetch_sub(layout.size(), Ordering::Relaxed);
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAllocator = PeakAllocator;
+
+fn report_peak_memory<F: FnOnce()>(name: &str, f: F) {
+    let baseline = CURRENT.load(Ordering::Relaxed);
+    PEAK.store(baseline, Ordering::Relaxed);
+    f();
+    let peak = PEAK.load(Ordering::Relaxed) - baseline;
+    println!(
+        "{name}: peak heap {:.1} MiB",
+        peak as f64 / (1024.0 * 1024.0)
+    );
+}
+
+fn rows() -> Vec<u64> {
+    env::var("BENCH_ROWS")
+        .ok()
+        .map(|rows| {
+            rows.split(',')
+                .map(|row| {
+                    row.trim()
+                        .parse()
+                        .expect("BENCH_ROWS must be comma separated")
+                })
+                .collect()
+        })
+        .unwrap_or_else(|| DEFAULT_ROWS.to_vec())
+}
+
+/// Writes the input to a temp file once, so no benchmark holds all of it in memory;
+/// every iteration streams it from disk instead.
+fn generate(rows: u64, dispute_rate: f64) -> PathBuf {
+    let path = env::temp_dir().join(format!(
+        "glowing-fiesta-bench-{}-{rows}-{dispute_rate}.csv",
+        process::id()
+    ));
+    let file = File::create(&path).expect("Failed to create the bench input");
+    TransactionGenerator::new(GeneratorConfig {
+        clients: 65_535,
+        transactions: rows,
+        dispute_rate,
+        resolve_rate: dispute_rate * 0.7,
+        chargeback_rate: dispute_rate * 0.2,
+        seed: 1,
+        ..GeneratorConfig::default()
+    })
+    .write_to(file)
+    .expect("Failed to write the bench input");
+    path
+}
+
+fn open(path: &Path) -> BufReader<File> {
+    BufReader::new(File::open(path).expect("Failed to open the bench input"))
+}
+
+fn parse(path: &Path) -> usize {
+    TransactionReader::new(open(path)).iter().count()
+}
+
+fn apply(mut ledger: Ledger, path: &Path) -> Ledger {
+    for transaction in TransactionReader::new(open(path)).iter() {
+        let _ = ledger.process(&transaction);
+    }
+    ledger
+}
+
+fn run(path: &Path) {
+    LedgerSystem::new(Ledger::default(), open(path), io::sink())
+        .run()
+        .unwrap();
+}
+
+fn bench_throughput(c: &mut Criterion) {
+    let mut group = c.benchmark_group("throughput");
+    group.sample_size(10);
+    for rows in rows() {
+        for &dispute_rate in DISPUTE_RATES {
+            let input = generate(rows, dispute_rate);
+            let parameter = format!("{rows}_rows/{dispute_rate}_disputes");
+            group.throughput(Throughput::Elements(rows));
+
+            report_peak_memory(&format!("parse/{parameter}"), || {
+                parse(&input);
+            });
+            group.bench_with_input(BenchmarkId::new("parse", &parameter), &input, |b, input| {
+                b.iter(|| parse(input))
+            });
+
+            // Includes parsing; subtract `parse` for the cost of applying alone.
+            report_peak_memory(&format!("process/{parameter}"), || {
+                apply(Ledger::default(), &input);
+            });
+            group.bench_with_input(
+                BenchmarkId::new("process", &parameter),
+                &input,
+                |b, input| {
+                    b.iter_batched(
+                        Ledger::default,
+                        |ledger| apply(ledger, input),
+                        BatchSize::PerIteration,
+                    )
+                },
+            );
+
+            report_peak_memory(&format!("run/{parameter}"), || run(&input));
+            group.bench_with_input(BenchmarkId::new("run", &parameter), &input, |b, input| {
+                b.iter(|| run(input))
+            });
+            let _ = fs::remove_file(&input);
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_throughput);
+criterion_main!(benches);
@@ -0,0 +1,39 @@
+#![no_main]
+
+//! Feeds arbitrary partner bytes through the same path `LedgerSystem::run` takes.
+//!
+//! Run with `cargo +nightly fuzz run ledger fuzz/corpus/ledger`.
+
+use glowing_fiesta::ledger::Ledger;
+use glowing_fiesta::transaction_reader::TransactionReader;
+use glowing_fiesta::verification::Violation;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let mut ledger = Ledger::default();
+    let mut transactions = TransactionReader::new(data);
+
+    for transaction in transactions.iter() {
+        let _ = ledger.process(&transaction);
+    }
+
+    // Negative available balances and locked accounts with leftover disputes are
+    // reachable under the current rules, and sums across clients can outgrow a
+    // Decimal even when every account fits; anything else means money was
+    // created or destroyed.
+    if let Err(report) = ledger.verify() {
+        for violation in report.violations() {
+            assert!(
+                matches!(
+                    violation,
+                    Violation::NegativeAvailable { .. }
+                        | Violation::LockedWithOpenDisputes { .. }
+                        | Violation::BalanceOverflow
+                ),
+                "{report}"
+            );
+        }
+    }
+
+    let _ = ledger.write_accounts(std::io::sink());
+});
@@ -1,34 +1,153 @@
+use crate::error::{Coded, ErrorCode};
+use crate::id::{ClientId, TxId};
+use crate::lock_policy::LockReason;
 use crate::stored_transaction::StoredTransaction;
+use crate::verification::Violation;
+use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
-use serde::Serialize;
+use serde::ser::SerializeStruct;
+use serde::{Serialize, Serializer};
 use std::collections::HashMap;
+use std::fmt;
 use thiserror::Error;
 
-#[derive(Debug, Error, PartialEq)]
+#[derive(Debug, Clone, Error, PartialEq)]
 pub enum Error {
     #[error("Account ({client}) has insufficient funds")]
-    InsufficientFunds { client: u16 },
+    InsufficientFunds { client: ClientId },
     #[error("Account ({client}) is locked")]
-    AccountLocked { client: u16 },
+    AccountLocked { client: ClientId },
+    #[error("Account ({client}) is frozen")]
+    AccountFrozen { client: ClientId },
+    #[error("Account ({client}) is closed")]
+    AccountClosed { client: ClientId },
+    #[error("Account ({client}) cannot be closed while it has held funds")]
+    FundsHeld { client: ClientId },
     #[error("Account ({client}) already has a dispute for transaction {tx}")]
-    TransactionAlreadyDisputed { client: u16, tx: u32 },
+    TransactionAlreadyDisputed { client: ClientId, tx: TxId },
     #[error(
         "Account ({client}) has a dispute for withdrawal transaction {tx}, which is not allowed"
     )]
-    DisputeOnWithdrawal { client: u16, tx: u32 },
+    DisputeOnWithdrawal { client: ClientId, tx: TxId },
     #[error("Account ({client}) does not have a dispute for transaction {tx}")]
-    DisputeNotFound { client: u16, tx: u32 },
+    DisputeNotFound { client: ClientId, tx: TxId },
+    #[error("Account ({client}) balance would overflow")]
+    Overflow { client: ClientId },
 }
 
-#[derive(Debug, PartialEq, Serialize)]
+impl Error {
+    pub fn client(&self) -> ClientId {
+        match self {
+            Error::InsufficientFunds { client }
+            | Error::AccountLocked { client }
+            | Error::AccountFrozen { client }
+            | Error::AccountClosed { client }
+            | Error::FundsHeld { client }
+            | Error::TransactionAlreadyDisputed { client, .. }
+            | Error::DisputeOnWithdrawal { client, .. }
+            | Error::DisputeNotFound { client, .. }
+            | Error::Overflow { client } => *client,
+        }
+    }
+
+    /// The disputed transaction, for dispute errors.
+    pub fn tx(&self) -> Option<TxId> {
+        match self {
+            Error::TransactionAlreadyDisputed { tx, .. }
+            | Error::DisputeOnWithdrawal { tx, .. }
+            | Error::DisputeNotFound { tx, .. } => Some(*tx),
+            Error::InsufficientFunds { .. }
+            | Error::AccountLocked { .. }
+            | Error::AccountFrozen { .. }
+            | Error::AccountClosed { .. }
+            | Error::FundsHeld { .. }
+            | Error::Overflow { .. } => None,
+        }
+    }
+}
+
+impl Coded for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::InsufficientFunds { .. } => ErrorCode::InsufficientFunds,
+            Error::AccountLocked { .. } => ErrorCode::AccountLocked,
+            Error::AccountFrozen { .. } => ErrorCode::AccountFrozen,
+            Error::AccountClosed { .. } => ErrorCode::AccountClosed,
+            Error::FundsHeld { .. } => ErrorCode::FundsHeld,
+            Error::TransactionAlreadyDisputed { .. } => ErrorCode::AlreadyDisputed,
+            Error::DisputeOnWithdrawal { .. } => ErrorCode::DisputeOnWithdrawal,
+            Error::DisputeNotFound { .. } => ErrorCode::DisputeNotFound,
+            Error::Overflow { .. } => ErrorCode::Overflow,
+        }
+    }
+}
+
+/// Where an account is in its lifecycle.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AccountStatus {
+    /// Opened but awaiting KYC approval; the ledger may cap its deposits.
+    PendingKyc,
+    Active,
+    /// Disputes proceed, but no funds move in or out until it is activated again.
+    Frozen,
+    /// Locked by a chargeback or a lock policy, for good.
+    Locked,
+    /// Closed with its balance paid out, for good.
+    Closed,
+}
+
+impl AccountStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AccountStatus::PendingKyc => "pending-kyc",
+            AccountStatus::Active => "active",
+            AccountStatus::Frozen => "frozen",
+            AccountStatus::Locked => "locked",
+            AccountStatus::Closed => "closed",
+        }
+    }
+}
+
+impl fmt::Display for AccountStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct AccountState {
-    client: u16,
+    client: ClientId,
     available: Decimal,
     held: Decimal,
     total: Decimal,
-    locked: bool,
-    #[serde(skip)]
-    disputes: HashMap<u32, Decimal>,
+    status: AccountStatus,
+    /// Why a [`LockPolicy`](crate::lock_policy::LockPolicy) locked the account. Locks
+    /// caused by a chargeback have no reason.
+    lock_reason: Option<LockReason>,
+    disputes: HashMap<TxId, Decimal>,
+    last_timestamp: Option<DateTime<Utc>>,
+    deposits: u64,
+    /// Sum of every deposit applied to the account.
+    deposited: Decimal,
+    chargebacks: u64,
+}
+
+/// Serialized as `client, available, held, total, locked, status, lock_reason`, keeping the
+/// `locked` flag for readers that predate the status. `lock_reason` is empty, or null in
+/// JSON, unless a lock policy locked the account.
+impl Serialize for AccountState {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("AccountState", 7)?;
+        state.serialize_field("client", &self.client)?;
+        state.serialize_field("available", &self.available)?;
+        state.serialize_field("held", &self.held)?;
+        state.serialize_field("total", &self.total)?;
+        state.serialize_field("locked", &self.locked())?;
+        state.serialize_field("status", &self.status)?;
+        state.serialize_field("lock_reason", &self.lock_reason)?;
+        state.end()
+    }
 }
 
 /*
@@ -40,50 +159,68 @@ pub enum Dispute {
 */
 
 impl AccountState {
-    pub fn new(client: u16) -> Self {
+    /// The names of the serialized fields in order, which head every CSV of accounts.
+    pub const FIELDS: [&'static str; 7] = [
+        "client",
+        "available",
+        "held",
+        "total",
+        "locked",
+        "status",
+        "lock_reason",
+    ];
+
+    pub fn new(client: ClientId) -> Self {
         AccountState {
             client,
             available: Decimal::ZERO,
             held: Decimal::ZERO,
             total: Decimal::ZERO,
-            locked: false,
+            status: AccountStatus::Active,
+            lock_reason: None,
             disputes: HashMap::new(),
+            last_timestamp: None,
+            deposits: 0,
+            deposited: Decimal::ZERO,
+            chargebacks: 0,
         }
     }
 
-    pub fn deposit(&mut self, amount: Decimal) -> Result<(), Error> {
-        if self.locked {
-            return Err(Error::AccountLocked {
-                client: self.client,
-            });
+    /// A new account awaiting KYC approval.
+    pub fn pending_kyc(client: ClientId) -> Self {
+        AccountState {
+            status: AccountStatus::PendingKyc,
+            ..AccountState::new(client)
         }
-        self.available += amount;
-        self.total += amount;
+    }
+
+    pub fn deposit(&mut self, amount: Decimal) -> Result<(), Error> {
+        self.check_movable()?;
+        let available = self.checked(self.available.checked_add(amount))?;
+        let total = self.checked(self.total.checked_add(amount))?;
+        self.available = available;
+        self.total = total;
+        self.deposits += 1;
+        self.deposited = self.deposited.saturating_add(amount);
         Ok(())
     }
 
     pub fn withdraw(&mut self, amount: Decimal) -> Result<(), Error> {
-        if self.locked {
-            return Err(Error::AccountLocked {
-                client: self.client,
-            });
-        }
+        self.check_movable()?;
         if amount > self.total {
             return Err(Error::InsufficientFunds {
                 client: self.client,
             });
         }
-        self.available -= amount;
-        self.total -= amount;
+        let available = self.checked(self.available.checked_sub(amount))?;
+        let total = self.checked(self.total.checked_sub(amount))?;
+        self.available = available;
+        self.total = total;
         Ok(())
     }
 
     pub fn dispute(&mut self, stored_transaction: &StoredTransaction) -> Result<(), Error> {
-        if self.locked {
-            return Err(Error::AccountLocked {
-                client: self.client,
-            });
-        }
+        self.check_usable()?;
         if self.disputes.contains_key(&stored_transaction.tx()) {
             let client = self.client;
             let tx = stored_transaction.tx();
@@ -91,8 +228,10 @@ impl AccountState {
         }
         match stored_transaction {
             StoredTransaction::Deposit(deposit) => {
-                self.available -= deposit.amount;
-                self.held += deposit.amount;
+                let available = self.checked(self.available.checked_sub(deposit.amount))?;
+                let held = self.checked(self.held.checked_add(deposit.amount))?;
+                self.available = available;
+                self.held = held;
                 self.disputes.insert(deposit.tx, deposit.amount);
             }
             StoredTransaction::Withdrawal(withdrawal) => {
@@ -108,15 +247,191 @@ impl AccountState {
         Ok(())
     }
 
-    pub fn resolve(&mut self, tx: u32) -> Result<(), Error> {
-        if self.locked {
-            return Err(Error::AccountLocked {
+    pub fn client(&self) -> ClientId {
+        self.client
+    }
+
+    pub fn available(&self) -> Decimal {
+        self.available
+    }
+
+    pub fn held(&self) -> Decimal {
+        self.held
+    }
+
+    pub fn total(&self) -> Decimal {
+        self.total
+    }
+
+    pub fn locked(&self) -> bool {
+        self.status == AccountStatus::Locked
+    }
+
+    pub fn status(&self) -> AccountStatus {
+        self.status
+    }
+
+    pub fn lock_reason(&self) -> Option<&LockReason> {
+        self.lock_reason.as_ref()
+    }
+
+    /// Locks the account for a reason other than a chargeback. Disputes already open on
+    /// the account can still be resolved or charged back, so the held funds are released.
+    pub fn lock(&mut self, reason: LockReason) {
+        self.status = AccountStatus::Locked;
+        self.lock_reason = Some(reason);
+    }
+
+    /// Moves a pending or frozen account to active; an active one stays as it is.
+    pub fn activate(&mut self) -> Result<(), Error> {
+        self.check_usable()?;
+        self.status = AccountStatus::Active;
+        Ok(())
+    }
+
+    /// Stops deposits and withdrawals until the account is activated again.
+    pub fn freeze(&mut self) -> Result<(), Error> {
+        self.check_usable()?;
+        self.status = AccountStatus::Frozen;
+        Ok(())
+    }
+
+    /// Closes the account and returns the available balance to pay out. Refused while
+    /// any funds are held or the balance is negative.
+    pub fn close(&mut self) -> Result<Decimal, Error> {
+        self.check_movable()?;
+        if !self.held.is_zero() {
+            return Err(Error::FundsHeld {
+                client: self.client,
+            });
+        }
+        if self.available < Decimal::ZERO {
+            return Err(Error::InsufficientFunds {
+                client: self.client,
+            });
+        }
+        let payout = self.available;
+        self.available = Decimal::ZERO;
+        self.total = Decimal::ZERO;
+        self.status = AccountStatus::Closed;
+        Ok(payout)
+    }
+
+    /// Deposits applied to the account.
+    pub fn deposit_count(&self) -> u64 {
+        self.deposits
+    }
+
+    /// Sum of the deposits applied to the account.
+    pub fn deposited(&self) -> Decimal {
+        self.deposited
+    }
+
+    /// Chargebacks applied to the account.
+    pub fn chargeback_count(&self) -> u64 {
+        self.chargebacks
+    }
+
+    /// Disputes raised against the account and not yet resolved or charged back.
+    pub fn open_disputes(&self) -> usize {
+        self.disputes.len()
+    }
+
+    /// The amount held for the transaction's open dispute.
+    pub fn disputed_amount(&self, tx: TxId) -> Option<Decimal> {
+        self.disputes.get(&tx).copied()
+    }
+
+    /// The latest timestamp of any transaction applied to this account.
+    pub fn last_timestamp(&self) -> Option<DateTime<Utc>> {
+        self.last_timestamp
+    }
+
+    pub fn observe_timestamp(&mut self, timestamp: DateTime<Utc>) {
+        self.last_timestamp = self.last_timestamp.max(Some(timestamp));
+    }
+
+    /// Rejects anything on a locked or closed account.
+    fn check_usable(&self) -> Result<(), Error> {
+        let client = self.client;
+        match self.status {
+            AccountStatus::Locked => Err(Error::AccountLocked { client }),
+            AccountStatus::Closed => Err(Error::AccountClosed { client }),
+            AccountStatus::PendingKyc | AccountStatus::Active | AccountStatus::Frozen => Ok(()),
+        }
+    }
+
+    /// Rejects settling a dispute on a closed account or one locked by a chargeback.
+    fn check_settleable(&self) -> Result<(), Error> {
+        if self.locked() && self.lock_reason.is_some() {
+            return Ok(());
+        }
+        self.check_usable()
+    }
+
+    /// Rejects moving funds in or out of an account that is not pending KYC or active.
+    fn check_movable(&self) -> Result<(), Error> {
+        self.check_usable()?;
+        if self.status == AccountStatus::Frozen {
+            return Err(Error::AccountFrozen {
+                client: self.client,
+            });
+        }
+        Ok(())
+    }
+
+    fn checked(&self, value: Option<Decimal>) -> Result<Decimal, Error> {
+        value.ok_or(Error::Overflow {
+            client: self.client,
+        })
+    }
+
+    /// Checks the invariants that must hold for this account in isolation.
+    pub fn violations(&self) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        if Some(self.total) != self.available.checked_add(self.held) {
+            violations.push(Violation::TotalMismatch {
+                client: self.client,
+                available: self.available,
+                held: self.held,
+                total: self.total,
+            });
+        }
+        let disputed = self
+            .disputes
+            .values()
+            .try_fold(Decimal::ZERO, |sum, amount| sum.checked_add(*amount))
+            .unwrap_or(Decimal::MAX);
+        if self.held != disputed {
+            violations.push(Violation::HeldMismatch {
+                client: self.client,
+                held: self.held,
+                disputed,
+            });
+        }
+        if self.available < Decimal::ZERO {
+            violations.push(Violation::NegativeAvailable {
+                client: self.client,
+                available: self.available,
+            });
+        }
+        if self.locked() && self.lock_reason.is_none() && !self.disputes.is_empty() {
+            violations.push(Violation::LockedWithOpenDisputes {
                 client: self.client,
+                disputes: self.disputes.len(),
             });
         }
-        if let Some(amount) = self.disputes.remove(&tx) {
-            self.available += amount;
-            self.held -= amount;
+        violations
+    }
+
+    pub fn resolve(&mut self, tx: TxId) -> Result<(), Error> {
+        self.check_settleable()?;
+        if let Some(&amount) = self.disputes.get(&tx) {
+            let available = self.checked(self.available.checked_add(amount))?;
+            let held = self.checked(self.held.checked_sub(amount))?;
+            self.available = available;
+            self.held = held;
+            self.disputes.remove(&tx);
             Ok(())
         } else {
             Err(Error::DisputeNotFound {
@@ -126,16 +441,16 @@ impl AccountState {
         }
     }
 
-    pub fn chargeback(&mut self, tx: u32) -> Result<(), Error> {
-        if self.locked {
-            return Err(Error::AccountLocked {
-                client: self.client,
-            });
-        }
-        if let Some(amount) = self.disputes.remove(&tx) {
-            self.held -= amount;
-            self.total -= amount;
-            self.locked = true;
+    pub fn chargeback(&mut self, tx: TxId) -> Result<(), Error> {
+        self.check_settleable()?;
+        if let Some(&amount) = self.disputes.get(&tx) {
+            let held = self.checked(self.held.checked_sub(amount))?;
+            let total = self.checked(self.total.checked_sub(amount))?;
+            self.held = held;
+            self.total = total;
+            self.status = AccountStatus::Locked;
+            self.chargebacks += 1;
+            self.disputes.remove(&tx);
             Ok(())
         } else {
             Err(Error::DisputeNotFound {
@@ -154,7 +469,7 @@ mod tests {
     #[test]
     fn test_deposit_on_fresh_account() {
         // given ...
-        let mut account = AccountState::new(1);
+        let mut account = AccountState::new(ClientId(1));
 
         // when ...
         let result = account.deposit(Decimal::new(100, 2));
@@ -169,14 +484,19 @@ mod tests {
     #[test]
     fn test_deposit_on_locked_account() {
         // given ...
-        let mut account = AccountState::new(1);
-        account.locked = true;
+        let mut account = AccountState::new(ClientId(1));
+        account.status = AccountStatus::Locked;
 
         // when ...
         let result = account.deposit(Decimal::new(100, 2));
 
         // then ...
-        assert_eq!(result, Err(Error::AccountLocked { client: 1 }));
+        assert_eq!(
+            result,
+            Err(Error::AccountLocked {
+                client: ClientId(1)
+            })
+        );
         assert_eq!(account.available, Decimal::ZERO);
         assert_eq!(account.total, Decimal::ZERO);
         assert_eq!(account.held, Decimal::ZERO);
@@ -185,7 +505,7 @@ mod tests {
     #[test]
     fn test_withdraw_on_sufficiently_funded_account() {
         // given ...
-        let mut account = AccountState::new(1);
+        let mut account = AccountState::new(ClientId(1));
         account.deposit(Decimal::new(200, 2)).unwrap();
 
         // when ...
@@ -201,13 +521,18 @@ mod tests {
     #[test]
     fn test_withdraw_on_empty_account() {
         // given ...
-        let mut account = AccountState::new(1);
+        let mut account = AccountState::new(ClientId(1));
 
         // when ...
         let result = account.withdraw(Decimal::new(100, 2));
 
         // then ...
-        assert_eq!(result, Err(Error::InsufficientFunds { client: 1 }));
+        assert_eq!(
+            result,
+            Err(Error::InsufficientFunds {
+                client: ClientId(1)
+            })
+        );
         assert_eq!(account.available, Decimal::ZERO);
         assert_eq!(account.total, Decimal::ZERO);
         assert_eq!(account.held, Decimal::ZERO);
@@ -216,14 +541,19 @@ mod tests {
     #[test]
     fn test_over_withdraw_on_funded_account() {
         // given ...
-        let mut account = AccountState::new(1);
+        let mut account = AccountState::new(ClientId(1));
         account.deposit(Decimal::new(100, 2)).unwrap();
 
         // when ...
         let result = account.withdraw(Decimal::new(200, 2));
 
         // then ...
-        assert_eq!(result, Err(Error::InsufficientFunds { client: 1 }));
+        assert_eq!(
+            result,
+            Err(Error::InsufficientFunds {
+                client: ClientId(1)
+            })
+        );
         assert_eq!(account.available, Decimal::new(100, 2));
         assert_eq!(account.total, Decimal::new(100, 2));
         assert_eq!(account.held, Decimal::ZERO);
@@ -232,15 +562,20 @@ mod tests {
     #[test]
     fn test_withdrawal_on_locked_account() {
         // given ...
-        let mut account = AccountState::new(1);
+        let mut account = AccountState::new(ClientId(1));
         account.deposit(Decimal::new(100, 2)).unwrap();
-        account.locked = true;
+        account.status = AccountStatus::Locked;
 
         // when ...
         let result = account.withdraw(Decimal::new(50, 2));
 
         // then ...
-        assert_eq!(result, Err(Error::AccountLocked { client: 1 }));
+        assert_eq!(
+            result,
+            Err(Error::AccountLocked {
+                client: ClientId(1)
+            })
+        );
         assert_eq!(account.available, Decimal::new(100, 2));
         assert_eq!(account.total, Decimal::new(100, 2));
         assert_eq!(account.held, Decimal::ZERO);
@@ -249,12 +584,13 @@ mod tests {
     #[test]
     fn test_dispute_on_deposit() {
         // given ...
-        let mut account = AccountState::new(1);
+        let mut account = AccountState::new(ClientId(1));
         let amount = Decimal::new(100, 2);
         let deposit = StoredTransaction::Deposit(StoredDepositTransaction {
-            tx: 1,
-            client: 1,
+            tx: TxId(1),
+            client: ClientId(1),
             amount,
+            timestamp: None,
         });
         account.deposit(amount).unwrap();
 
@@ -266,18 +602,19 @@ mod tests {
         assert_eq!(account.available, Decimal::new(0, 2));
         assert_eq!(account.held, Decimal::new(100, 2));
         assert_eq!(account.total, Decimal::new(100, 2));
-        assert_eq!(account.disputes.get(&1), Some(&Decimal::new(100, 2)));
+        assert_eq!(account.disputes.get(&TxId(1)), Some(&Decimal::new(100, 2)));
     }
 
     #[test]
     fn test_dispute_on_withdrawal() {
         // given ...
-        let mut account = AccountState::new(1);
+        let mut account = AccountState::new(ClientId(1));
         let amount = Decimal::new(100, 2);
         let withdrawal = StoredTransaction::Withdrawal(StoredWithdrawalTransaction {
-            tx: 1,
-            client: 1,
+            tx: TxId(1),
+            client: ClientId(1),
             amount,
+            timestamp: None,
         });
         account.deposit(amount).unwrap();
         account.withdraw(amount).unwrap();
@@ -286,7 +623,13 @@ mod tests {
         let result = account.dispute(&withdrawal);
 
         // then ...
-        assert_eq!(result, Err(Error::DisputeOnWithdrawal { client: 1, tx: 1 }));
+        assert_eq!(
+            result,
+            Err(Error::DisputeOnWithdrawal {
+                client: ClientId(1),
+                tx: TxId(1)
+            })
+        );
         assert_eq!(account.available, Decimal::new(0, 2));
         assert_eq!(account.held, Decimal::ZERO);
         assert_eq!(account.total, Decimal::ZERO);
@@ -296,21 +639,27 @@ mod tests {
     #[test]
     fn test_dispute_on_locked_account() {
         // given ...
-        let mut account = AccountState::new(1);
+        let mut account = AccountState::new(ClientId(1));
         let amount = Decimal::new(100, 2);
         let deposit = StoredTransaction::Deposit(StoredDepositTransaction {
-            tx: 1,
-            client: 1,
+            tx: TxId(1),
+            client: ClientId(1),
             amount,
+            timestamp: None,
         });
         account.deposit(amount).unwrap();
-        account.locked = true;
+        account.status = AccountStatus::Locked;
 
         // when ...
         let result = account.dispute(&deposit);
 
         // then ...
-        assert_eq!(result, Err(Error::AccountLocked { client: 1 }));
+        assert_eq!(
+            result,
+            Err(Error::AccountLocked {
+                client: ClientId(1)
+            })
+        );
         assert_eq!(account.available, Decimal::new(100, 2));
         assert_eq!(account.held, Decimal::ZERO);
         assert_eq!(account.total, Decimal::new(100, 2));
@@ -320,64 +669,77 @@ mod tests {
     #[test]
     fn test_resolve_dispute() {
         // given ...
-        let mut account = AccountState::new(1);
+        let mut account = AccountState::new(ClientId(1));
         let amount = Decimal::new(100, 2);
         let deposit = StoredTransaction::Deposit(StoredDepositTransaction {
-            tx: 1,
-            client: 1,
+            tx: TxId(1),
+            client: ClientId(1),
             amount,
+            timestamp: None,
         });
         account.deposit(amount).unwrap();
         account.dispute(&deposit).unwrap();
 
         // when ...
-        let result = account.resolve(1);
+        let result = account.resolve(TxId(1));
 
         // then ...
         assert_eq!(result, Ok(()));
         assert_eq!(account.available, Decimal::new(100, 2));
         assert_eq!(account.held, Decimal::ZERO);
         assert_eq!(account.total, Decimal::new(100, 2));
-        assert!(!account.disputes.contains_key(&1));
+        assert!(!account.disputes.contains_key(&TxId(1)));
     }
 
     #[test]
     fn test_resolve_on_locked_account() {
         // given ...
-        let mut account = AccountState::new(1);
+        let mut account = AccountState::new(ClientId(1));
         let amount = Decimal::new(100, 2);
         let deposit = StoredTransaction::Deposit(StoredDepositTransaction {
-            tx: 1,
-            client: 1,
+            tx: TxId(1),
+            client: ClientId(1),
             amount,
+            timestamp: None,
         });
         account.deposit(amount).unwrap();
         account.dispute(&deposit).unwrap();
-        account.locked = true;
+        account.status = AccountStatus::Locked;
 
         // when ...
-        let result = account.resolve(1);
+        let result = account.resolve(TxId(1));
 
         // then ...
-        assert_eq!(result, Err(Error::AccountLocked { client: 1 }));
+        assert_eq!(
+            result,
+            Err(Error::AccountLocked {
+                client: ClientId(1)
+            })
+        );
         assert_eq!(account.available, Decimal::new(0, 2));
         assert_eq!(account.held, Decimal::new(100, 2));
         assert_eq!(account.total, Decimal::new(100, 2));
-        assert!(account.disputes.contains_key(&1));
+        assert!(account.disputes.contains_key(&TxId(1)));
     }
 
     #[test]
     fn test_resolve_on_missing_dispute() {
         // given ...
-        let mut account = AccountState::new(1);
+        let mut account = AccountState::new(ClientId(1));
         let amount = Decimal::new(100, 2);
         account.deposit(amount).unwrap();
 
         // when ...
-        let result = account.resolve(1);
+        let result = account.resolve(TxId(1));
 
         // then ...
-        assert_eq!(result, Err(Error::DisputeNotFound { client: 1, tx: 1 }));
+        assert_eq!(
+            result,
+            Err(Error::DisputeNotFound {
+                client: ClientId(1),
+                tx: TxId(1)
+            })
+        );
         assert_eq!(account.available, Decimal::new(100, 2));
         assert_eq!(account.held, Decimal::ZERO);
         assert_eq!(account.total, Decimal::new(100, 2));
@@ -386,67 +748,287 @@ mod tests {
     #[test]
     fn test_chargeback() {
         // given ...
-        let mut account = AccountState::new(1);
+        let mut account = AccountState::new(ClientId(1));
         let amount = Decimal::new(100, 2);
         let deposit = StoredTransaction::Deposit(StoredDepositTransaction {
-            tx: 1,
-            client: 1,
+            tx: TxId(1),
+            client: ClientId(1),
             amount,
+            timestamp: None,
         });
         account.deposit(amount).unwrap();
         account.dispute(&deposit).unwrap();
 
         // when ...
-        let result = account.chargeback(1);
+        let result = account.chargeback(TxId(1));
 
         // then ...
         assert_eq!(result, Ok(()));
         assert_eq!(account.available, Decimal::ZERO);
         assert_eq!(account.held, Decimal::ZERO);
         assert_eq!(account.total, Decimal::ZERO);
-        assert!(account.locked);
-        assert!(!account.disputes.contains_key(&1));
+        assert!(account.locked());
+        assert!(!account.disputes.contains_key(&TxId(1)));
     }
 
     #[test]
     fn test_chargeback_on_locked_account() {
         // given ...
-        let mut account = AccountState::new(1);
+        let mut account = AccountState::new(ClientId(1));
         let amount = Decimal::new(100, 2);
         let deposit = StoredTransaction::Deposit(StoredDepositTransaction {
-            tx: 1,
-            client: 1,
+            tx: TxId(1),
+            client: ClientId(1),
             amount,
+            timestamp: None,
         });
         account.deposit(amount).unwrap();
         account.dispute(&deposit).unwrap();
-        account.locked = true;
+        account.status = AccountStatus::Locked;
 
         // when ...
-        let result = account.chargeback(1);
+        let result = account.chargeback(TxId(1));
 
         // then ...
-        assert_eq!(result, Err(Error::AccountLocked { client: 1 }));
+        assert_eq!(
+            result,
+            Err(Error::AccountLocked {
+                client: ClientId(1)
+            })
+        );
         assert_eq!(account.available, Decimal::new(0, 2));
         assert_eq!(account.held, Decimal::new(100, 2));
         assert_eq!(account.total, Decimal::new(100, 2));
-        assert!(account.disputes.contains_key(&1));
+        assert!(account.disputes.contains_key(&TxId(1)));
     }
 
     #[test]
     fn test_chargeback_on_missing_dispute() {
         // given ...
-        let mut account = AccountState::new(1);
+        let mut account = AccountState::new(ClientId(1));
         let amount = Decimal::new(100, 2);
         account.deposit(amount).unwrap();
 
         // when ...
-        let result = account.chargeback(1);
+        let result = account.chargeback(TxId(1));
 
         // then ...
-        assert_eq!(result, Err(Error::DisputeNotFound { client: 1, tx: 1 }));
+        assert_eq!(
+            result,
+            Err(Error::DisputeNotFound {
+                client: ClientId(1),
+                tx: TxId(1)
+            })
+        );
         assert_eq!(account.available, Decimal::new(100, 2));
         assert_eq!(account.held, Decimal::ZERO);
         assert_eq!(account.total, Decimal::new(100, 2));
     }
+
+    #[test]
+    fn test_violations_on_consistent_account() {
+        // given ...
+        let mut account = AccountState::new(ClientId(1));
+        let amount = Decimal::new(100, 2);
+        let deposit = StoredTransaction::Deposit(StoredDepositTransaction {
+            tx: TxId(1),
+            client: ClientId(1),
+            amount,
+            timestamp: None,
+        });
+        account.deposit(amount).unwrap();
+        account.dispute(&deposit).unwrap();
+
+        // when ...
+        let violations = account.violations();
+
+        // then ...
+        assert_eq!(violations, vec![]);
+    }
+
+    #[test]
+    fn test_violations_on_negative_available() {
+        // given ...
+        let mut account = AccountState::new(ClientId(1));
+        let amount = Decimal::new(100, 2);
+        let deposit = StoredTransaction::Deposit(StoredDepositTransaction {
+            tx: TxId(1),
+            client: ClientId(1),
+            amount,
+            timestamp: None,
+        });
+        account.deposit(amount).unwrap();
+        account.dispute(&deposit).unwrap();
+        account.withdraw(amount).unwrap();
+
+        // when ...
+        let violations = account.violations();
+
+        // then ...
+        assert_eq!(
+            violations,
+            vec![Violation::NegativeAvailable {
+                client: ClientId(1),
+                available: Decimal::new(-100, 2),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_violations_on_corrupted_balances() {
+        // given ...
+        let mut account = AccountState::new(ClientId(1));
+        account.total = Decimal::new(100, 2);
+        account.held = Decimal::new(50, 2);
+        account.status = AccountStatus::Locked;
+        account.disputes.insert(TxId(1), Decimal::new(25, 2));
+
+        // when ...
+        let violations = account.violations();
+
+        // then ...
+        assert_eq!(
+            violations,
+            vec![
+                Violation::TotalMismatch {
+                    client: ClientId(1),
+                    available: Decimal::ZERO,
+                    held: Decimal::new(50, 2),
+                    total: Decimal::new(100, 2),
+                },
+                Violation::HeldMismatch {
+                    client: ClientId(1),
+                    held: Decimal::new(50, 2),
+                    disputed: Decimal::new(25, 2),
+                },
+                Violation::LockedWithOpenDisputes {
+                    client: ClientId(1),
+                    disputes: 1,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_deposit_overflow() {
+        // given ...
+        let mut account = AccountState::new(ClientId(1));
+        account.deposit(Decimal::MAX).unwrap();
+
+        // when ...
+        let result = account.deposit(Decimal::ONE);
+
+        // then ...
+        assert_eq!(
+            result,
+            Err(Error::Overflow {
+                client: ClientId(1)
+            })
+        );
+        assert_eq!(account.available, Decimal::MAX);
+        assert_eq!(account.total, Decimal::MAX);
+        assert_eq!(account.held, Decimal::ZERO);
+    }
+
+    #[test]
+    fn test_dispute_overflow() {
+        // given ...
+        let mut account = AccountState::new(ClientId(1));
+        account.deposit(Decimal::MAX).unwrap();
+        account.withdraw(Decimal::MAX).unwrap();
+        account.deposit(Decimal::MAX).unwrap();
+        let deposit = StoredTransaction::Deposit(StoredDepositTransaction {
+            tx: TxId(1),
+            client: ClientId(1),
+            amount: Decimal::MAX,
+            timestamp: None,
+        });
+        account.dispute(&deposit).unwrap();
+        account.withdraw(Decimal::MAX).unwrap();
+        let second = StoredTransaction::Deposit(StoredDepositTransaction {
+            tx: TxId(2),
+            client: ClientId(1),
+            amount: Decimal::MAX,
+            timestamp: None,
+        });
+
+        // when ...
+        let result = account.dispute(&second);
+
+        // then ...
+        assert_eq!(
+            result,
+            Err(Error::Overflow {
+                client: ClientId(1)
+            })
+        );
+        assert_eq!(account.available, -Decimal::MAX);
+        assert_eq!(account.held, Decimal::MAX);
+        assert!(!account.disputes.contains_key(&TxId(2)));
+    }
+
+    #[test]
+    fn test_close_pays_out_available_balance() {
+        // given ...
+        let mut account = AccountState::new(ClientId(1));
+        account.deposit(Decimal::new(100, 2)).unwrap();
+        let deposit = StoredTransaction::Deposit(StoredDepositTransaction {
+            tx: TxId(1),
+            client: ClientId(1),
+            amount: Decimal::new(40, 2),
+            timestamp: None,
+        });
+        account.dispute(&deposit).unwrap();
+
+        // when ...
+        let held = account.close();
+        account.resolve(TxId(1)).unwrap();
+        let payout = account.close();
+
+        // then ...
+        assert_eq!(
+            held,
+            Err(Error::FundsHeld {
+                client: ClientId(1)
+            })
+        );
+        assert_eq!(payout, Ok(Decimal::new(100, 2)));
+        assert_eq!(account.status(), AccountStatus::Closed);
+        assert_eq!(account.total, Decimal::ZERO);
+        assert_eq!(
+            account.deposit(Decimal::ONE),
+            Err(Error::AccountClosed {
+                client: ClientId(1)
+            })
+        );
+    }
+
+    #[test]
+    fn test_frozen_account_moves_no_funds() {
+        // given ...
+        let mut account = AccountState::pending_kyc(ClientId(1));
+        account.deposit(Decimal::new(100, 2)).unwrap();
+
+        // when ...
+        account.freeze().unwrap();
+        let withdrawal = account.withdraw(Decimal::ONE);
+        let close = account.close();
+        account.activate().unwrap();
+
+        // then ...
+        assert_eq!(
+            withdrawal,
+            Err(Error::AccountFrozen {
+                client: ClientId(1)
+            })
+        );
+        assert_eq!(
+            close,
+            Err(Error::AccountFrozen {
+                client: ClientId(1)
+            })
+        );
+        assert_eq!(account.status(), AccountStatus::Active);
+        assert_eq!(account.withdraw(Decimal::ONE), Ok(()));
+    }
 }
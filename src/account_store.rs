@@ -1,16 +1,46 @@
 use crate::account_state::AccountState;
+use crate::id::ClientId;
 use std::collections::HashMap;
 
 #[derive(Debug, Default)]
 pub struct AccountStore {
-    accounts: HashMap<u16, AccountState>,
+    accounts: HashMap<ClientId, AccountState>,
 }
 
 impl AccountStore {
-    pub fn get_or_create(&mut self, client_id: u16) -> &mut AccountState {
-        self.accounts
-            .entry(client_id)
-            .or_insert_with(|| AccountState::new(client_id))
+    pub fn get(&self, client_id: ClientId) -> Option<&AccountState> {
+        self.accounts.get(&client_id)
+    }
+
+    pub fn get_mut(&mut self, client_id: ClientId) -> Option<&mut AccountState> {
+        self.accounts.get_mut(&client_id)
+    }
+
+    /// Adds a newly opened account for a client that has none.
+    pub fn insert(&mut self, account: AccountState) {
+        let client = account.client();
+        debug_assert!(
+            !self.accounts.contains_key(&client),
+            "{client} already has an account"
+        );
+        self.accounts.insert(client, account);
+    }
+
+    /// Puts back a previously cloned account, replacing whatever state it has now.
+    pub fn restore(&mut self, account: AccountState) {
+        self.accounts.insert(account.client(), account);
+    }
+
+    pub fn remove(&mut self, client_id: ClientId) {
+        self.accounts.remove(&client_id);
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.is_empty()
     }
 
     pub fn iter(&self) -> impl Iterator<Item = &AccountState> {
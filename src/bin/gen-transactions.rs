@@ -0,0 +1,61 @@
+use clap::Parser;
+use glowing_fiesta::transaction_generator::{GeneratorConfig, TransactionGenerator};
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+
+/// Writes a synthetic transactions CSV for load tests and benchmarks.
+#[derive(Debug, Parser)]
+#[command(name = "gen-transactions")]
+struct Args {
+    /// Number of distinct clients to spread transactions across.
+    #[arg(long, default_value_t = 1000)]
+    clients: u64,
+    /// Number of rows to write, excluding the header.
+    #[arg(long, default_value_t = 10_000)]
+    transactions: u64,
+    /// Probability that a row disputes an earlier deposit.
+    #[arg(long, default_value_t = 0.01)]
+    dispute_rate: f64,
+    /// Probability that a row resolves an open dispute.
+    #[arg(long, default_value_t = 0.007)]
+    resolve_rate: f64,
+    /// Probability that a row charges back an open dispute.
+    #[arg(long, default_value_t = 0.002)]
+    chargeback_rate: f64,
+    /// Probability that a row is malformed and should be rejected by the reader.
+    #[arg(long, default_value_t = 0.0)]
+    malformed_rate: f64,
+    /// Seed for the random generator; the same seed always produces the same file.
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+    /// Output file; defaults to stdout.
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+fn main() -> anyhow::Result<()> {
+    let args = Args::parse();
+    for rate in [
+        args.dispute_rate,
+        args.resolve_rate,
+        args.chargeback_rate,
+        args.malformed_rate,
+    ] {
+        anyhow::ensure!((0.0..=1.0).contains(&rate), "Rates must be between 0 and 1");
+    }
+    let generator = TransactionGenerator::new(GeneratorConfig {
+        clients: args.clients,
+        transactions: args.transactions,
+        dispute_rate: args.dispute_rate,
+        resolve_rate: args.resolve_rate,
+        chargeback_rate: args.chargeback_rate,
+        malformed_rate: args.malformed_rate,
+        seed: args.seed,
+    });
+    match args.output {
+        Some(path) => generator.write_to(File::create(path)?)?,
+        None => generator.write_to(io::stdout().lock())?,
+    }
+    Ok(())
+}
@@ -0,0 +1,247 @@
+use crate::error::{Coded, ErrorCode};
+use crate::id::{ClientId, ClientNames};
+use log::{error, info};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("line {line}: invalid client id {id:?}")]
+    InvalidClient { line: usize, id: String },
+}
+
+impl Coded for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::Io(_) => ErrorCode::Io,
+            Error::InvalidClient { .. } => ErrorCode::InvalidConfig,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Blocklist {
+    entries: HashMap<String, String>,
+    /// The entries that are internal client ids, so lookups need not format the id.
+    clients: HashMap<ClientId, String>,
+    names: Option<ClientNames>,
+    source: Option<Source>,
+}
+
+#[derive(Debug)]
+struct Source {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    checked: Instant,
+    interval: Duration,
+}
+
+impl Blocklist {
+    pub fn from_clients(clients: impl IntoIterator<Item = ClientId>) -> Self {
+        Self::from_entries(
+            clients
+                .into_iter()
+                .map(|client| (client.to_string(), String::new()))
+                .collect(),
+        )
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Ok(Self::from_entries(Self::parse(&fs::read_to_string(path)?)?))
+    }
+
+    fn from_entries(entries: HashMap<String, String>) -> Self {
+        let mut blocklist = Blocklist::default();
+        blocklist.set_entries(entries);
+        blocklist
+    }
+
+    fn set_entries(&mut self, entries: HashMap<String, String>) {
+        self.clients = entries
+            .iter()
+            .filter_map(|(id, note)| {
+                let client = id
+                    .parse()
+                    .ok()
+                    .filter(|client: &u64| client.to_string() == *id)?;
+                Some((ClientId(client), note.clone()))
+            })
+            .collect();
+        self.entries = entries;
+    }
+
+    /// Reloads the file when its modification time changes, checking at most once per
+    /// `interval`.
+    pub fn watch(path: &Path, interval: Duration) -> Result<Self, Error> {
+        let mut blocklist = Self::load(path)?;
+        blocklist.source = Some(Source {
+            path: path.to_path_buf(),
+            modified: modified(path).ok(),
+            checked: Instant::now(),
+            interval,
+        });
+        Ok(blocklist)
+    }
+
+    pub fn with_client_names(mut self, names: ClientNames) -> Self {
+        self.names = Some(names);
+        self
+    }
+
+    /// Whitespace inside an id is usually a note missing its `#`.
+    fn parse(list: &str) -> Result<HashMap<String, String>, Error> {
+        let mut entries = HashMap::new();
+        for (line, text) in (1..).zip(list.lines()) {
+            let (id, note) = text.split_once('#').unwrap_or((text, ""));
+            let id = id.trim();
+            if id.is_empty() {
+                continue;
+            }
+            if id.contains(char::is_whitespace) {
+                return Err(Error::InvalidClient {
+                    line,
+                    id: id.to_string(),
+                });
+            }
+            entries.insert(id.to_string(), note.trim().to_string());
+        }
+        Ok(entries)
+    }
+
+    pub fn get(&self, client: ClientId) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        let note = match &self.names {
+            Some(names) => names.with(client, |id| self.entries.get(id))?,
+            None => self.clients.get(&client),
+        };
+        note.map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn is_watched(&self) -> bool {
+        self.source.is_some()
+    }
+
+    /// A file that cannot be read or parsed is logged and the current list stays in force.
+    pub fn refresh(&mut self) {
+        let Some(source) = self.source.as_mut() else {
+            return;
+        };
+        if source.checked.elapsed() < source.interval {
+            return;
+        }
+        source.checked = Instant::now();
+        let modified = modified(&source.path).ok();
+        if modified == source.modified {
+            return;
+        }
+        let reloaded = fs::read_to_string(&source.path)
+            .map_err(Error::from)
+            .and_then(|list| Self::parse(&list));
+        match reloaded {
+            Ok(entries) => {
+                source.modified = modified;
+                info!(
+                    "Reloaded blocklist {} with {} clients",
+                    source.path.display(),
+                    entries.len()
+                );
+                self.set_entries(entries);
+            }
+            Err(e) => error!("Failed to reload blocklist {}: {e}", source.path.display()),
+        }
+    }
+}
+
+fn modified(path: &Path) -> io::Result<SystemTime> {
+    fs::metadata(path)?.modified()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::IdMap;
+
+    #[test]
+    fn test_parse_blocklist() {
+        // given ...
+        let list = "# sanctioned\n\n7\n42  # OFAC SDN, 2024-05-01\n";
+
+        // when ...
+        let entries = Blocklist::parse(list).unwrap();
+
+        // then ...
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries["7"], "");
+        assert_eq!(entries["42"], "OFAC SDN, 2024-05-01");
+        assert!(matches!(
+            Blocklist::parse("7\n42 OFAC\n"),
+            Err(Error::InvalidClient { line: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn test_blocklist_of_internal_ids() {
+        // given ...
+        let list = "7 # fraud\n0012\nbob\n";
+
+        // when ...
+        let blocklist = Blocklist::from_entries(Blocklist::parse(list).unwrap());
+
+        // then ...
+        assert_eq!(blocklist.get(ClientId(7)), Some("fraud"));
+        assert_eq!(blocklist.get(ClientId(12)), None);
+        assert_eq!(blocklist.get(ClientId(1)), None);
+    }
+
+    #[test]
+    fn test_blocklist_of_partner_ids() {
+        // given ...
+        let mut ids = IdMap::default();
+        let alice = ids.client("alice");
+        let blocklist = Blocklist::from_entries(Blocklist::parse("bob # sanctioned\n1\n").unwrap())
+            .with_client_names(ids.client_names());
+
+        // when ...
+        let bob = ids.client("bob");
+
+        // then ...
+        assert_eq!(blocklist.get(alice), None);
+        assert_eq!(blocklist.get(bob), Some("sanctioned"));
+    }
+
+    #[test]
+    fn test_watched_blocklist_reloads() {
+        // given ...
+        let path = std::env::temp_dir().join(format!("blocklist-{}.txt", std::process::id()));
+        fs::write(&path, "1\n").unwrap();
+        let mut blocklist = Blocklist::watch(&path, Duration::ZERO).unwrap();
+        let file = fs::File::options().write(true).open(&path).unwrap();
+
+        // when ...
+        fs::write(&path, "2 # added\n").unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(60))
+            .unwrap();
+        blocklist.refresh();
+        fs::remove_file(&path).unwrap();
+
+        // then ...
+        assert_eq!(blocklist.get(ClientId(1)), None);
+        assert_eq!(blocklist.get(ClientId(2)), Some("added"));
+    }
+}
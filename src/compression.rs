@@ -0,0 +1,242 @@
+use flate2::Compression;
+use flate2::bufread::MultiGzDecoder;
+use flate2::write::GzEncoder;
+use std::io;
+use std::io::{BufRead, Read, Write};
+use std::path::Path;
+
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// A reader that transparently decompresses gzip or zstd input, detected by its magic
+/// bytes, and passes anything else through untouched. Decompression is streaming.
+pub enum Decoder<R> {
+    Plain(Peeked<R>),
+    Gzip(MultiGzDecoder<Peeked<R>>),
+    Zstd(zstd::Decoder<'static, Peeked<R>>),
+    /// Setting up the decoder failed; the error is returned by the first read.
+    Failed(Option<io::Error>),
+}
+
+/// The reader with the bytes read to detect the format put back in front of it.
+pub type Peeked<R> = io::Chain<io::Cursor<Vec<u8>>, R>;
+
+impl<R: BufRead> Decoder<R> {
+    pub fn new(mut reader: R) -> Self {
+        // Pipes may hand over fewer bytes than the longest magic in one read.
+        let mut head = vec![0; ZSTD_MAGIC.len()];
+        let mut len = 0;
+        while len < head.len() {
+            match reader.read(&mut head[len..]) {
+                Ok(0) => break,
+                Ok(read) => len += read,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => return Decoder::Failed(Some(e)),
+            }
+        }
+        head.truncate(len);
+        let (gzip, zstd) = (head.starts_with(GZIP_MAGIC), head.starts_with(ZSTD_MAGIC));
+        let reader = io::Cursor::new(head).chain(reader);
+        if gzip {
+            Decoder::Gzip(MultiGzDecoder::new(reader))
+        } else if zstd {
+            zstd::Decoder::with_buffer(reader)
+                .map_or_else(|e| Decoder::Failed(Some(e)), Decoder::Zstd)
+        } else {
+            Decoder::Plain(reader)
+        }
+    }
+}
+
+impl<R: BufRead> Read for Decoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Decoder::Plain(reader) => reader.read(buf),
+            Decoder::Gzip(reader) => reader.read(buf),
+            Decoder::Zstd(reader) => reader.read(buf),
+            Decoder::Failed(e) => match e.take() {
+                Some(e) => Err(e),
+                None => Ok(0),
+            },
+        }
+    }
+}
+
+/// A writer that compresses according to the output path's extension: `.gz` for gzip,
+/// `.zst` for zstd and no compression otherwise. The compressed stream is only complete
+/// once [`Encoder::finish`] is called; dropping the encoder leaves it truncated.
+pub enum Encoder<W: Write> {
+    Plain(W),
+    Gzip(GzEncoder<W>),
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> Encoder<W> {
+    pub fn for_path(path: &Path, writer: W) -> io::Result<Self> {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("gz") => Ok(Encoder::Gzip(GzEncoder::new(
+                writer,
+                Compression::default(),
+            ))),
+            Some("zst") => Ok(Encoder::Zstd(zstd::Encoder::new(writer, 0)?)),
+            _ => Ok(Encoder::Plain(writer)),
+        }
+    }
+
+    /// Writes the end of the compressed stream, flushes it and returns the writer.
+    pub fn finish(self) -> io::Result<W> {
+        let mut writer = match self {
+            Encoder::Plain(writer) => writer,
+            Encoder::Gzip(writer) => writer.finish()?,
+            Encoder::Zstd(writer) => writer.finish()?,
+        };
+        writer.flush()?;
+        Ok(writer)
+    }
+}
+
+impl<W: Write> Write for Encoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Encoder::Plain(writer) => writer.write(buf),
+            Encoder::Gzip(writer) => writer.write(buf),
+            Encoder::Zstd(writer) => writer.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Encoder::Plain(writer) => writer.flush(),
+            Encoder::Gzip(writer) => writer.flush(),
+            Encoder::Zstd(writer) => writer.flush(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+
+    const DATA: &str = "type,client,tx,amount\ndeposit,1,1,1.0\n";
+
+    fn encode(extension: &str) -> Vec<u8> {
+        let path = Path::new("accounts").with_extension(extension);
+        let mut encoder = Encoder::for_path(&path, Vec::new()).unwrap();
+        encoder.write_all(DATA.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn decode(bytes: &[u8]) -> String {
+        let mut decoded = String::new();
+        Decoder::new(BufReader::new(bytes))
+            .read_to_string(&mut decoded)
+            .unwrap();
+        decoded
+    }
+
+    #[test]
+    fn test_gzip_round_trip() {
+        // given ...
+        let encoded = encode("csv.gz");
+
+        // when ...
+        let decoded = decode(&encoded);
+
+        // then ...
+        assert!(encoded.starts_with(GZIP_MAGIC));
+        assert_eq!(decoded, DATA);
+    }
+
+    #[test]
+    fn test_zstd_round_trip() {
+        // given ...
+        let encoded = encode("csv.zst");
+
+        // when ...
+        let decoded = decode(&encoded);
+
+        // then ...
+        assert!(encoded.starts_with(ZSTD_MAGIC));
+        assert_eq!(decoded, DATA);
+    }
+
+    #[test]
+    fn test_plain_passthrough() {
+        // given ...
+        let encoded = encode("csv");
+
+        // when ...
+        let decoded = decode(&encoded);
+
+        // then ...
+        assert_eq!(encoded, DATA.as_bytes());
+        assert_eq!(decoded, DATA);
+    }
+
+    /// A pipe that hands over one byte per read.
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let Some((&first, rest)) = self.0.split_first() else {
+                return Ok(0);
+            };
+            let Some(out) = buf.first_mut() else {
+                return Ok(0);
+            };
+            *out = first;
+            self.0 = rest;
+            Ok(1)
+        }
+    }
+
+    #[test]
+    fn test_magic_split_across_reads() {
+        for extension in ["csv.gz", "csv.zst", "csv"] {
+            // given ...
+            let encoded = encode(extension);
+
+            // when ...
+            let mut decoded = String::new();
+            let read = Decoder::new(BufReader::with_capacity(1, Trickle(&encoded)))
+                .read_to_string(&mut decoded);
+
+            // then ...
+            assert!(read.is_ok(), "{extension}");
+            assert_eq!(decoded, DATA, "{extension}");
+        }
+    }
+
+    /// A disk that is always full.
+    struct Full;
+
+    impl Write for Full {
+        fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+            Err(io::ErrorKind::StorageFull.into())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Err(io::ErrorKind::StorageFull.into())
+        }
+    }
+
+    #[test]
+    fn test_finish_reports_the_failed_end_of_stream() {
+        for extension in ["csv.gz", "csv.zst"] {
+            // given ...
+            let path = Path::new("accounts").with_extension(extension);
+            let encoder = Encoder::for_path(&path, Full).unwrap();
+
+            // when ...
+            let finished = encoder.finish();
+
+            // then ...
+            assert_eq!(
+                finished.err().map(|e| e.kind()),
+                Some(io::ErrorKind::StorageFull),
+                "{extension}"
+            );
+        }
+    }
+}
@@ -0,0 +1,284 @@
+use crate::error::{Coded, ErrorCode};
+use crate::transaction_type::TransactionType;
+use csv::StringRecord;
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use thiserror::Error;
+
+/// The column names of the format in the specification, plus the optional ones.
+const COLUMNS: [&str; 6] = ["type", "client", "tx", "amount", "timestamp", "batch"];
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Parse(#[from] toml::de::Error),
+    #[error("Dialect delimiter and quote must be ASCII characters")]
+    NotAscii,
+    #[error("Dialect order only applies to files without headers; set has_headers = false")]
+    OrderWithHeaders,
+    #[error("Dialect maps column {partner} to {column}, which is not one of {}", COLUMNS.join(", "))]
+    UnknownColumn { partner: String, column: String },
+    #[error(
+        "Dialect order names column {column}, which is neither mapped in columns nor one of {}",
+        COLUMNS.join(", ")
+    )]
+    UnknownOrderColumn { column: String },
+    #[error("Dialect order names column {column} more than once")]
+    DuplicateOrderColumn { column: String },
+}
+
+impl Coded for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::Io(_) => ErrorCode::Io,
+            Error::Parse(_)
+            | Error::NotAscii
+            | Error::OrderWithHeaders
+            | Error::UnknownColumn { .. }
+            | Error::UnknownOrderColumn { .. }
+            | Error::DuplicateOrderColumn { .. } => ErrorCode::InvalidConfig,
+        }
+    }
+}
+
+/// Describes how a partner's CSV differs from the format in the specification.
+///
+/// ```toml
+/// delimiter = ";"
+/// has_headers = true
+/// case_insensitive_types = true
+///
+/// [columns]
+/// txn_id = "tx"
+/// customer = "client"
+///
+/// [types]
+/// credit = "deposit"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Dialect {
+    pub delimiter: char,
+    pub quote: char,
+    pub has_headers: bool,
+    /// Column order for files without a header row. Only valid with `has_headers = false`.
+    pub order: Vec<String>,
+    /// Partner column name to canonical column name: `type`, `client`, `tx`, `amount`,
+    /// `timestamp` or `batch`.
+    pub columns: HashMap<String, String>,
+    /// Partner type value to transaction type.
+    pub types: HashMap<String, TransactionType>,
+    pub case_insensitive_types: bool,
+}
+
+impl Default for Dialect {
+    fn default() -> Self {
+        Dialect {
+            delimiter: ',',
+            quote: '"',
+            has_headers: true,
+            order: ["type", "client", "tx", "amount"]
+                .map(String::from)
+                .to_vec(),
+            columns: HashMap::new(),
+            types: HashMap::new(),
+            case_insensitive_types: false,
+        }
+    }
+}
+
+impl Dialect {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    fn parse(config: &str) -> Result<Self, Error> {
+        let dialect: Dialect = toml::from_str(config)?;
+        if !dialect.delimiter.is_ascii() || !dialect.quote.is_ascii() {
+            return Err(Error::NotAscii);
+        }
+        // An order other than the default can only have been set by the file.
+        if dialect.has_headers && dialect.order != Dialect::default().order {
+            return Err(Error::OrderWithHeaders);
+        }
+        let mut columns: Vec<_> = dialect.columns.iter().collect();
+        columns.sort();
+        if let Some((partner, column)) = columns
+            .into_iter()
+            .find(|(_, column)| !COLUMNS.contains(&column.as_str()))
+        {
+            return Err(Error::UnknownColumn {
+                partner: partner.clone(),
+                column: column.clone(),
+            });
+        }
+        // Entries are partner names like headers are, so they may be mapped too.
+        let mut ordered = HashSet::new();
+        for name in &dialect.order {
+            let column = dialect.columns.get(name).unwrap_or(name);
+            if !COLUMNS.contains(&column.as_str()) {
+                return Err(Error::UnknownOrderColumn {
+                    column: name.clone(),
+                });
+            }
+            if !ordered.insert(column) {
+                return Err(Error::DuplicateOrderColumn {
+                    column: column.clone(),
+                });
+            }
+        }
+        Ok(dialect)
+    }
+
+    pub fn reader_builder(&self) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .delimiter(self.delimiter as u8)
+            .quote(self.quote as u8)
+            .has_headers(self.has_headers)
+            .trim(csv::Trim::All)
+            .flexible(true);
+        builder
+    }
+
+    /// Renames partner headers to the canonical `type, client, tx, amount, timestamp, batch`
+    /// names. Files without headers are named by [`Dialect::order`] instead.
+    pub fn canonical_headers(&self, headers: Option<&StringRecord>) -> Option<StringRecord> {
+        let order;
+        let headers = if self.has_headers {
+            headers?
+        } else {
+            order = StringRecord::from(self.order.clone());
+            &order
+        };
+        if self.columns.is_empty() {
+            return Some(headers.clone());
+        }
+        Some(
+            headers
+                .iter()
+                .map(|header| self.columns.get(header).map_or(header, String::as_str))
+                .collect(),
+        )
+    }
+
+    pub fn rewrites_types(&self) -> bool {
+        self.case_insensitive_types || !self.types.is_empty()
+    }
+
+    /// Maps a partner type value to the lowercase name the deserializer expects.
+    pub fn normalize_type<'a>(&self, value: &'a str) -> Cow<'a, str> {
+        if let Some(r#type) = self.types.get(value) {
+            return Cow::Borrowed(r#type.name());
+        }
+        if self.case_insensitive_types {
+            let lower = value.to_lowercase();
+            return match self.types.get(&lower) {
+                Some(r#type) => Cow::Borrowed(r#type.name()),
+                None => Cow::Owned(lower),
+            };
+        }
+        Cow::Borrowed(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_dialect() {
+        // given ...
+        let config = "delimiter = \";\"\n\
+            case_insensitive_types = true\n\
+            [columns]\n\
+            txn_id = \"tx\"\n\
+            [types]\n\
+            credit = \"deposit\"\n";
+
+        // when ...
+        let dialect = Dialect::parse(config).unwrap();
+
+        // then ...
+        assert_eq!(dialect.delimiter, ';');
+        assert_eq!(dialect.quote, '"');
+        assert!(dialect.has_headers);
+        assert_eq!(dialect.normalize_type("CREDIT"), "deposit");
+        assert_eq!(dialect.normalize_type("Withdrawal"), "withdrawal");
+        let headers = StringRecord::from(vec!["type", "client", "txn_id", "amount"]);
+        assert_eq!(
+            dialect.canonical_headers(Some(&headers)),
+            Some(StringRecord::from(vec!["type", "client", "tx", "amount"]))
+        );
+    }
+
+    #[test]
+    fn test_order_requires_files_without_headers() {
+        // given ...
+        let config = "order = [\"client\", \"type\", \"tx\", \"amount\"]\n";
+
+        // when ...
+        let with_headers = Dialect::parse(config);
+        let without_headers = Dialect::parse(&format!("has_headers = false\n{config}"));
+
+        // then ...
+        assert!(matches!(with_headers, Err(Error::OrderWithHeaders)));
+        assert_eq!(without_headers.unwrap().order[0], "client");
+    }
+
+    #[test]
+    fn test_order_names_known_columns_once() {
+        // given ...
+        let config = "has_headers = false\n\
+            [columns]\n\
+            txn_id = \"tx\"\n";
+
+        // when ...
+        let mapped = Dialect::parse(&format!(
+            "order = [\"type\", \"client\", \"txn_id\", \"amount\"]\n{config}"
+        ));
+        let unknown = Dialect::parse(&format!(
+            "order = [\"type\", \"client\", \"txid\", \"amount\"]\n{config}"
+        ));
+        let duplicate = Dialect::parse(&format!(
+            "order = [\"type\", \"client\", \"tx\", \"txn_id\"]\n{config}"
+        ));
+
+        // then ...
+        assert_eq!(mapped.unwrap().order[2], "txn_id");
+        let unknown = unknown.unwrap_err();
+        assert_eq!(unknown.code(), ErrorCode::InvalidConfig);
+        assert_eq!(
+            unknown.to_string(),
+            "Dialect order names column txid, which is neither mapped in columns nor one of \
+            type, client, tx, amount, timestamp, batch"
+        );
+        assert!(matches!(duplicate, Err(Error::DuplicateOrderColumn { column }) if column == "tx"));
+    }
+
+    #[test]
+    fn test_columns_must_be_canonical() {
+        // given ...
+        let config = "[columns]\n\
+            txn_id = \"tx\"\n\
+            customer = \"customer_id\"\n";
+
+        // when ...
+        let result = Dialect::parse(config);
+
+        // then ...
+        let error = result.unwrap_err();
+        assert_eq!(error.code(), ErrorCode::InvalidConfig);
+        assert_eq!(
+            error.to_string(),
+            "Dialect maps column customer to customer_id, which is not one of \
+            type, client, tx, amount, timestamp, batch"
+        );
+    }
+}
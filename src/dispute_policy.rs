@@ -0,0 +1,240 @@
+use crate::error::{Coded, ErrorCode};
+use crate::id::ClientId;
+use crate::transaction_type::TransactionType;
+use chrono::TimeDelta;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Parse(#[from] toml::de::Error),
+}
+
+impl Coded for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::Io(_) => ErrorCode::Io,
+            Error::Parse(_) => ErrorCode::InvalidConfig,
+        }
+    }
+}
+
+/// How long transactions stay disputable and how long a dispute may stay open.
+///
+/// A client's tier window takes precedence over the window for the disputed transaction's
+/// type, which takes precedence over the default window. A client listed in several tiers
+/// gets the longest of their windows. Without any window, or when either transaction has
+/// no timestamp, disputes are never too late.
+#[derive(Debug, Clone, Default)]
+pub struct DisputePolicy {
+    window: Option<TimeDelta>,
+    type_windows: HashMap<TransactionType, TimeDelta>,
+    client_windows: HashMap<ClientId, TimeDelta>,
+    auto_resolve_after: Option<TimeDelta>,
+}
+
+/// The TOML form of a [`DisputePolicy`], with every duration in seconds.
+///
+/// ```toml
+/// window = 10368000
+/// auto_resolve_after = 5184000
+///
+/// [types]
+/// deposit = 10368000
+///
+/// [tiers.gold]
+/// window = 15552000
+/// clients = [1, 2, 3]
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    window: Option<u32>,
+    auto_resolve_after: Option<u32>,
+    types: HashMap<TransactionType, u32>,
+    tiers: HashMap<String, Tier>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Tier {
+    window: u32,
+    clients: Vec<ClientId>,
+}
+
+fn seconds(seconds: u32) -> TimeDelta {
+    TimeDelta::seconds(seconds.into())
+}
+
+impl DisputePolicy {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    fn parse(config: &str) -> Result<Self, Error> {
+        let config: Config = toml::from_str(config)?;
+        let mut policy = DisputePolicy::default();
+        if let Some(window) = config.window {
+            policy = policy.with_window(seconds(window));
+        }
+        if let Some(after) = config.auto_resolve_after {
+            policy = policy.with_auto_resolve_after(seconds(after));
+        }
+        for (r#type, window) in config.types {
+            policy = policy.with_type_window(r#type, seconds(window));
+        }
+        let mut client_windows: HashMap<ClientId, u32> = HashMap::new();
+        for tier in config.tiers.into_values() {
+            for client in tier.clients {
+                let window = client_windows.entry(client).or_default();
+                *window = tier.window.max(*window);
+            }
+        }
+        for (client, window) in client_windows {
+            policy = policy.with_client_window(client, seconds(window));
+        }
+        Ok(policy)
+    }
+
+    pub fn with_window(mut self, window: TimeDelta) -> Self {
+        self.window = Some(window);
+        self
+    }
+
+    pub fn with_type_window(mut self, r#type: TransactionType, window: TimeDelta) -> Self {
+        self.type_windows.insert(r#type, window);
+        self
+    }
+
+    pub fn with_client_window(mut self, client: ClientId, window: TimeDelta) -> Self {
+        self.client_windows.insert(client, window);
+        self
+    }
+
+    /// Resolves disputes automatically once they have been open this long, measured
+    /// against the timestamps of later transactions.
+    pub fn with_auto_resolve_after(mut self, after: TimeDelta) -> Self {
+        self.auto_resolve_after = Some(after);
+        self
+    }
+
+    pub fn window(&self, client: ClientId, r#type: TransactionType) -> Option<TimeDelta> {
+        self.client_windows
+            .get(&client)
+            .or_else(|| self.type_windows.get(&r#type))
+            .or(self.window.as_ref())
+            .copied()
+    }
+
+    pub fn auto_resolve_after(&self) -> Option<TimeDelta> {
+        self.auto_resolve_after
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_policy() {
+        // given ...
+        let config = "window = 60\n\
+            auto_resolve_after = 30\n\
+            [types]\n\
+            withdrawal = 10\n\
+            [tiers.gold]\n\
+            window = 120\n\
+            clients = [7]\n";
+
+        // when ...
+        let policy = DisputePolicy::parse(config).unwrap();
+
+        // then ...
+        assert_eq!(policy.auto_resolve_after(), Some(TimeDelta::seconds(30)));
+        assert_eq!(
+            policy.window(ClientId(1), TransactionType::Deposit),
+            Some(TimeDelta::seconds(60))
+        );
+        assert_eq!(
+            policy.window(ClientId(1), TransactionType::Withdrawal),
+            Some(TimeDelta::seconds(10))
+        );
+        assert_eq!(
+            policy.window(ClientId(7), TransactionType::Withdrawal),
+            Some(TimeDelta::seconds(120))
+        );
+    }
+
+    #[test]
+    fn test_longest_tier_window_wins() {
+        // given ...
+        let config = "[types]\n\
+            deposit = 500\n\
+            [tiers.bronze]\n\
+            window = 60\n\
+            clients = [7, 8]\n\
+            [tiers.gold]\n\
+            window = 120\n\
+            clients = [7]\n\
+            [tiers.silver]\n\
+            window = 90\n\
+            clients = [7]\n";
+
+        // when ...
+        let policy = DisputePolicy::parse(config).unwrap();
+
+        // then ...
+        assert_eq!(
+            policy.window(ClientId(7), TransactionType::Deposit),
+            Some(TimeDelta::seconds(120))
+        );
+        assert_eq!(
+            policy.window(ClientId(8), TransactionType::Deposit),
+            Some(TimeDelta::seconds(60))
+        );
+        assert_eq!(
+            policy.window(ClientId(9), TransactionType::Deposit),
+            Some(TimeDelta::seconds(500))
+        );
+    }
+
+    #[test]
+    fn test_window_precedence() {
+        // given ...
+        let policy = DisputePolicy::default()
+            .with_window(TimeDelta::days(90))
+            .with_type_window(TransactionType::Deposit, TimeDelta::days(120))
+            .with_client_window(ClientId(7), TimeDelta::days(180));
+
+        // when ...
+        let gold = policy.window(ClientId(7), TransactionType::Deposit);
+        let deposit = policy.window(ClientId(1), TransactionType::Deposit);
+        let withdrawal = policy.window(ClientId(1), TransactionType::Withdrawal);
+
+        // then ...
+        assert_eq!(gold, Some(TimeDelta::days(180)));
+        assert_eq!(deposit, Some(TimeDelta::days(120)));
+        assert_eq!(withdrawal, Some(TimeDelta::days(90)));
+    }
+
+    #[test]
+    fn test_invalid_policy() {
+        // given ...
+        let config = "window = -60\n";
+
+        // when ...
+        let result = DisputePolicy::parse(config);
+
+        // then ...
+        let error = result.unwrap_err();
+        assert!(matches!(error, Error::Parse(_)));
+        assert_eq!(error.code(), ErrorCode::InvalidConfig);
+    }
+}
@@ -0,0 +1,136 @@
+use serde::{Serialize, Serializer};
+use std::fmt;
+
+/// Broad kind of failure, for routing errors without looking at individual codes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Category {
+    /// The row could not be read as a transaction.
+    Parse,
+    /// The transaction is incomplete or conflicts with the ledger's records.
+    Validation,
+    /// The transaction is well formed but the account rules refuse it.
+    Business,
+    /// Reading input or writing output failed.
+    Io,
+    /// A configuration file is not valid.
+    Config,
+    /// The ledger broke one of its own invariants.
+    Internal,
+}
+
+/// Stable machine-readable identifier of every error the pipeline reports. The codes
+/// never change meaning, so they are safe to count, alert and route on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum ErrorCode {
+    MalformedRow,
+    InvalidField,
+    MissingAmount,
+    DuplicateTransaction,
+    OutOfOrder,
+    DisputeTransactionNotFound,
+    DisputeUnownedTransaction,
+    AccountNotOpen,
+    AccountAlreadyOpen,
+    AccountNotFound,
+    DisputeWindowExpired,
+    RuleViolation,
+    ClientBlocked,
+    InsufficientFunds,
+    AccountLocked,
+    AccountFrozen,
+    AccountClosed,
+    FundsHeld,
+    KycDepositCap,
+    AlreadyDisputed,
+    DisputeOnWithdrawal,
+    DisputeNotFound,
+    Overflow,
+    Io,
+    InvalidConfig,
+    InvariantViolation,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::MalformedRow => "E_MALFORMED_ROW",
+            ErrorCode::InvalidField => "E_INVALID_FIELD",
+            ErrorCode::MissingAmount => "E_MISSING_AMOUNT",
+            ErrorCode::DuplicateTransaction => "E_DUPLICATE_TRANSACTION",
+            ErrorCode::OutOfOrder => "E_OUT_OF_ORDER",
+            ErrorCode::DisputeTransactionNotFound => "E_DISPUTE_TRANSACTION_NOT_FOUND",
+            ErrorCode::DisputeUnownedTransaction => "E_DISPUTE_UNOWNED_TRANSACTION",
+            ErrorCode::AccountNotOpen => "E_ACCOUNT_NOT_OPEN",
+            ErrorCode::AccountAlreadyOpen => "E_ACCOUNT_ALREADY_OPEN",
+            ErrorCode::AccountNotFound => "E_ACCOUNT_NOT_FOUND",
+            ErrorCode::DisputeWindowExpired => "E_DISPUTE_WINDOW_EXPIRED",
+            ErrorCode::RuleViolation => "E_RULE_VIOLATION",
+            ErrorCode::ClientBlocked => "E_CLIENT_BLOCKED",
+            ErrorCode::InsufficientFunds => "E_INSUFFICIENT_FUNDS",
+            ErrorCode::AccountLocked => "E_ACCOUNT_LOCKED",
+            ErrorCode::AccountFrozen => "E_ACCOUNT_FROZEN",
+            ErrorCode::AccountClosed => "E_ACCOUNT_CLOSED",
+            ErrorCode::FundsHeld => "E_FUNDS_HELD",
+            ErrorCode::KycDepositCap => "E_KYC_DEPOSIT_CAP",
+            ErrorCode::AlreadyDisputed => "E_ALREADY_DISPUTED",
+            ErrorCode::DisputeOnWithdrawal => "E_DISPUTE_ON_WITHDRAWAL",
+            ErrorCode::DisputeNotFound => "E_DISPUTE_NOT_FOUND",
+            ErrorCode::Overflow => "E_OVERFLOW",
+            ErrorCode::Io => "E_IO",
+            ErrorCode::InvalidConfig => "E_INVALID_CONFIG",
+            ErrorCode::InvariantViolation => "E_INVARIANT_VIOLATION",
+        }
+    }
+
+    pub fn category(&self) -> Category {
+        match self {
+            ErrorCode::MalformedRow | ErrorCode::InvalidField => Category::Parse,
+            ErrorCode::MissingAmount
+            | ErrorCode::DuplicateTransaction
+            | ErrorCode::OutOfOrder
+            | ErrorCode::DisputeTransactionNotFound
+            | ErrorCode::DisputeUnownedTransaction
+            | ErrorCode::AccountNotOpen
+            | ErrorCode::AccountAlreadyOpen
+            | ErrorCode::AccountNotFound => Category::Validation,
+            ErrorCode::DisputeWindowExpired
+            | ErrorCode::RuleViolation
+            | ErrorCode::ClientBlocked
+            | ErrorCode::InsufficientFunds
+            | ErrorCode::AccountLocked
+            | ErrorCode::AccountFrozen
+            | ErrorCode::AccountClosed
+            | ErrorCode::FundsHeld
+            | ErrorCode::KycDepositCap
+            | ErrorCode::AlreadyDisputed
+            | ErrorCode::DisputeOnWithdrawal
+            | ErrorCode::DisputeNotFound
+            | ErrorCode::Overflow => Category::Business,
+            ErrorCode::Io => Category::Io,
+            ErrorCode::InvalidConfig => Category::Config,
+            ErrorCode::InvariantViolation => Category::Internal,
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ErrorCode {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+/// Errors that carry an [`ErrorCode`].
+pub trait Coded {
+    fn code(&self) -> ErrorCode;
+
+    fn category(&self) -> Category {
+        self.code().category()
+    }
+}
@@ -0,0 +1,175 @@
+use crate::account_state::AccountStatus;
+use crate::error::ErrorCode;
+use crate::id::{ClientId, TxId};
+use crate::ledger;
+use crate::lock_policy::LockReason;
+use rust_decimal::Decimal;
+use serde::{Serialize, Serializer};
+use std::fmt;
+use std::io;
+
+/// What a processed transaction changed, published to every registered [`Observer`].
+///
+/// Events of a batch are only published once the whole batch has been applied; a
+/// rejected batch publishes nothing but the rejection of the failing member.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event")]
+pub enum Event {
+    /// Opened or activated by an `open` transaction, leaving it in `status`. Implicitly
+    /// opened accounts have no event of their own.
+    AccountOpened {
+        client: ClientId,
+        tx: TxId,
+        status: AccountStatus,
+    },
+    /// Closed by a `close` transaction, paying out `amount`.
+    AccountClosed {
+        client: ClientId,
+        tx: TxId,
+        amount: Decimal,
+    },
+    AccountFrozen {
+        client: ClientId,
+    },
+    FundsDeposited {
+        client: ClientId,
+        tx: TxId,
+        amount: Decimal,
+    },
+    FundsWithdrawn {
+        client: ClientId,
+        tx: TxId,
+        amount: Decimal,
+    },
+    FundsHeld {
+        client: ClientId,
+        tx: TxId,
+        amount: Decimal,
+    },
+    DisputeResolved {
+        client: ClientId,
+        tx: TxId,
+        amount: Decimal,
+        automatic: bool,
+    },
+    FundsChargedBack {
+        client: ClientId,
+        tx: TxId,
+        amount: Decimal,
+    },
+    /// Locked by a chargeback, or by a lock policy limit when there is a reason.
+    AccountLocked {
+        client: ClientId,
+        reason: Option<LockReason>,
+    },
+    /// Applied, but broke a risk rule whose action is to flag for review.
+    TransactionFlagged {
+        client: ClientId,
+        tx: TxId,
+        rule: String,
+    },
+    TransactionRejected {
+        client: ClientId,
+        tx: TxId,
+        code: ErrorCode,
+        #[serde(serialize_with = "display")]
+        reason: ledger::Error,
+    },
+}
+
+fn display<T, S>(value: &T, serializer: S) -> Result<S::Ok, S::Error>
+where
+    T: fmt::Display,
+    S: Serializer,
+{
+    serializer.collect_str(value)
+}
+
+pub trait Observer {
+    fn notify(&mut self, event: &Event);
+
+    /// Writes out anything buffered once the run is over, failing if any event was lost.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<F> Observer for F
+where
+    F: FnMut(&Event),
+{
+    fn notify(&mut self, event: &Event) {
+        self(event)
+    }
+}
+
+/// Writes every event as one line of JSON.
+pub struct JsonLinesObserver<W> {
+    writer: W,
+    /// The first failed write, reported again by [`Observer::flush`].
+    failed: Option<io::Error>,
+}
+
+impl<W: io::Write> JsonLinesObserver<W> {
+    pub fn new(writer: W) -> Self {
+        JsonLinesObserver {
+            writer,
+            failed: None,
+        }
+    }
+}
+
+impl<W: io::Write> Observer for JsonLinesObserver<W> {
+    fn notify(&mut self, event: &Event) {
+        let written = serde_json::to_writer(&mut self.writer, event)
+            .map_err(io::Error::from)
+            .and_then(|()| self.writer.write_all(b"\n"));
+        if let Err(e) = written {
+            log::error!("Failed to write event: {e}");
+            self.failed.get_or_insert(e);
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.failed.take() {
+            Some(e) => Err(e),
+            None => self.writer.flush(),
+        }
+    }
+}
+
+/// The observers registered with a ledger.
+#[derive(Default)]
+pub(crate) struct Observers(Vec<Box<dyn Observer + Send>>);
+
+impl Observers {
+    pub(crate) fn push(&mut self, observer: Box<dyn Observer + Send>) {
+        self.0.push(observer);
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Flushes every observer, returning the first failure.
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        self.0
+            .iter_mut()
+            .map(|observer| observer.flush())
+            .fold(Ok(()), Result::and)
+    }
+
+    pub(crate) fn publish(&mut self, events: impl IntoIterator<Item = Event>) {
+        for event in events {
+            for observer in &mut self.0 {
+                observer.notify(&event);
+            }
+        }
+    }
+}
+
+impl fmt::Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} observer(s)", self.0.len())
+    }
+}
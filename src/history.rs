@@ -0,0 +1,173 @@
+use crate::account_state::AccountState;
+use crate::id::{ClientId, TxId};
+use crate::lock_policy::LockReason;
+use crate::statement::StatementLine;
+use crate::stored_transaction::StoredTransaction;
+use crate::transaction::Transaction;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PointInTime {
+    /// Right after this deposit or withdrawal was applied.
+    Transaction(TxId),
+    /// Untimestamped transactions count as happening when their neighbours did.
+    Timestamp(DateTime<Utc>),
+}
+
+#[derive(Debug, Default)]
+pub struct History {
+    events: HashMap<ClientId, Vec<Transaction>>,
+    /// Policy locks and API freezes, by the number of events recorded when they happened.
+    locks: HashMap<ClientId, Vec<(usize, LockReason)>>,
+    freezes: HashMap<ClientId, Vec<usize>>,
+}
+
+impl History {
+    pub fn record(&mut self, transaction: Transaction) {
+        self.events
+            .entry(transaction.client())
+            .or_default()
+            .push(transaction);
+    }
+
+    pub fn record_lock(&mut self, client: ClientId, reason: LockReason) {
+        let at = self.len(client);
+        self.locks.entry(client).or_default().push((at, reason));
+    }
+
+    pub fn record_freeze(&mut self, client: ClientId) {
+        let at = self.len(client);
+        self.freezes.entry(client).or_default().push(at);
+    }
+
+    pub fn len(&self, client: ClientId) -> usize {
+        self.events.get(&client).map_or(0, Vec::len)
+    }
+
+    pub fn truncate(&mut self, client: ClientId, len: usize) {
+        if let Some(events) = self.events.get_mut(&client) {
+            events.truncate(len);
+        }
+        if let Some(locks) = self.locks.get_mut(&client) {
+            locks.retain(|&(at, _)| at <= len);
+        }
+        if let Some(freezes) = self.freezes.get_mut(&client) {
+            freezes.retain(|&at| at <= len);
+        }
+    }
+
+    /// KYC is not part of the history, so a replayed account that is not frozen, locked
+    /// or closed is always active.
+    pub fn account_at(&self, client: ClientId, at: PointInTime) -> Option<AccountState> {
+        let events = self.events.get(&client)?;
+        let end = match at {
+            PointInTime::Transaction(tx) => {
+                events.iter().position(|event| {
+                    matches!(event, Transaction::Deposit(_) | Transaction::Withdrawal(_))
+                        && event.tx() == tx
+                })? + 1
+            }
+            PointInTime::Timestamp(timestamp) => events
+                .iter()
+                .position(|event| event.timestamp().is_some_and(|at| at > timestamp))
+                .unwrap_or(events.len()),
+        };
+        if end == 0 {
+            return None;
+        }
+        Some(replay(
+            client,
+            &events[..end],
+            self.locks(client),
+            self.freezes(client),
+            |_, _, _| {},
+        ))
+    }
+
+    pub fn statement(&self, client: ClientId) -> Vec<StatementLine> {
+        let events = self.events.get(&client).map_or(&[][..], Vec::as_slice);
+        let mut lines = Vec::with_capacity(events.len());
+        replay(
+            client,
+            events,
+            self.locks(client),
+            self.freezes(client),
+            |event, amount, account| {
+                lines.push(StatementLine::new(event, amount, account));
+            },
+        );
+        lines
+    }
+
+    pub fn clients(&self) -> Vec<ClientId> {
+        let mut clients: Vec<_> = self.events.keys().copied().collect();
+        clients.sort();
+        clients
+    }
+
+    fn locks(&self, client: ClientId) -> &[(usize, LockReason)] {
+        self.locks.get(&client).map_or(&[][..], Vec::as_slice)
+    }
+
+    fn freezes(&self, client: ClientId) -> &[usize] {
+        self.freezes.get(&client).map_or(&[][..], Vec::as_slice)
+    }
+}
+
+/// Calls `visit` after each event with the amount it moved and the resulting account.
+fn replay<F>(
+    client: ClientId,
+    events: &[Transaction],
+    locks: &[(usize, LockReason)],
+    freezes: &[usize],
+    mut visit: F,
+) -> AccountState
+where
+    F: FnMut(&Transaction, Decimal, &AccountState),
+{
+    let mut account = AccountState::new(client);
+    let mut stored: HashMap<TxId, StoredTransaction> = HashMap::new();
+    for (index, event) in events.iter().enumerate() {
+        let amount = match event {
+            Transaction::Deposit(deposit) => deposit.amount,
+            Transaction::Withdrawal(withdrawal) => withdrawal.amount,
+            Transaction::Close(_) => account.available(),
+            Transaction::Open(_) => Decimal::ZERO,
+            _ => stored
+                .get(&event.tx())
+                .map_or(Decimal::ZERO, StoredTransaction::amount),
+        };
+        // Only applied transactions are recorded, so replaying them cannot fail.
+        let _ = match event {
+            Transaction::Deposit(deposit) => {
+                stored.insert(deposit.tx, deposit.into());
+                account.deposit(deposit.amount)
+            }
+            Transaction::Withdrawal(withdrawal) => {
+                stored.insert(withdrawal.tx, withdrawal.into());
+                account.withdraw(withdrawal.amount)
+            }
+            Transaction::Dispute(dispute) => match stored.get(&dispute.tx) {
+                Some(disputed) => account.dispute(disputed),
+                None => Ok(()),
+            },
+            Transaction::Resolve(resolve) => account.resolve(resolve.tx),
+            Transaction::Chargeback(chargeback) => account.chargeback(chargeback.tx),
+            Transaction::Close(_) => account.close().map(|_| ()),
+            Transaction::Open(_) => account.activate(),
+        };
+        if let Some(timestamp) = event.timestamp() {
+            account.observe_timestamp(timestamp);
+        }
+        if let Some((_, reason)) = locks.iter().find(|&&(at, _)| at == index + 1) {
+            account.lock(reason.clone());
+        }
+        if freezes.contains(&(index + 1)) {
+            let _ = account.freeze();
+        }
+        visit(event, amount, &account);
+    }
+    account
+}
@@ -0,0 +1,322 @@
+use crate::error::{Coded, ErrorCode};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::fmt;
+use std::io;
+use std::str::FromStr;
+use std::sync::{Arc, PoisonError, RwLock};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+    #[error("{kind} {external:?} is mapped to the reserved id 0")]
+    ReservedId { kind: IdKind, external: String },
+    #[error("{kind}s {first:?} and {second:?} are both mapped to {internal}")]
+    DuplicateId {
+        kind: IdKind,
+        internal: u64,
+        first: String,
+        second: String,
+    },
+}
+
+impl Coded for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::Csv(e) if e.is_io_error() => ErrorCode::Io,
+            Error::Csv(_) | Error::ReservedId { .. } | Error::DuplicateId { .. } => {
+                ErrorCode::InvalidConfig
+            }
+        }
+    }
+}
+
+macro_rules! numeric_id {
+    ($(#[$meta:meta])* $name:ident) => {
+        $(#[$meta])*
+        #[derive(
+            Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize,
+        )]
+        #[serde(transparent)]
+        pub struct $name(pub u64);
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                self.0.fmt(f)
+            }
+        }
+
+        impl From<u64> for $name {
+            fn from(id: u64) -> Self {
+                $name(id)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = std::num::ParseIntError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                s.parse().map($name)
+            }
+        }
+    };
+}
+
+numeric_id!(
+    /// Internal identifier of a client account.
+    ClientId
+);
+numeric_id!(
+    /// Internal identifier of a deposit or withdrawal, unique across all clients.
+    TxId
+);
+
+/// Which table of an [`IdMap`] a persisted id belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum IdKind {
+    Client,
+    Tx,
+}
+
+impl fmt::Display for IdKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IdKind::Client => f.write_str("client"),
+            IdKind::Tx => f.write_str("tx"),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IdRecord {
+    kind: IdKind,
+    external: String,
+    internal: u64,
+}
+
+/// Assigns internal numeric ids to partner-supplied string ids, in first-seen order.
+///
+/// The table is saved as a `kind,external,internal` CSV and loaded again on the next run,
+/// so a partner id keeps the same internal id for the lifetime of the ledger.
+#[derive(Debug, Default)]
+pub struct IdMap {
+    clients: HashMap<String, ClientId>,
+    txs: HashMap<String, TxId>,
+    last_client: u64,
+    last_tx: u64,
+    names: ClientNames,
+}
+
+/// The partner id of every client an [`IdMap`] knows, by internal id. Clones share the
+/// map's view, so they see clients it assigns later on.
+#[derive(Debug, Clone, Default)]
+pub struct ClientNames(Arc<RwLock<HashMap<ClientId, String>>>);
+
+impl ClientNames {
+    pub fn get(&self, client: ClientId) -> Option<String> {
+        self.with(client, str::to_string)
+    }
+
+    /// Calls `f` with the client's partner id, without copying it.
+    pub fn with<T>(&self, client: ClientId, f: impl FnOnce(&str) -> T) -> Option<T> {
+        let names = self.0.read().unwrap_or_else(PoisonError::into_inner);
+        names.get(&client).map(|name| f(name))
+    }
+
+    fn insert(&self, client: ClientId, external: &str) {
+        let mut names = self.0.write().unwrap_or_else(PoisonError::into_inner);
+        names.insert(client, external.to_string());
+    }
+}
+
+impl IdMap {
+    /// Never assigned to a partner id. Stands in for references to partner transactions
+    /// the map does not know, which the ledger then rejects as not found.
+    pub const UNKNOWN_TX: TxId = TxId(0);
+    /// Like [`IdMap::UNKNOWN_TX`], for references by partner clients the map does not know.
+    pub const UNKNOWN_CLIENT: ClientId = ClientId(0);
+
+    /// Loads a saved table, rejecting one that maps a partner id to a reserved id or two
+    /// partner ids to the same internal id.
+    pub fn load<R: io::Read>(reader: R) -> Result<Self, Error> {
+        let mut map = IdMap::default();
+        let mut seen = HashMap::new();
+        for record in csv::Reader::from_reader(reader).deserialize() {
+            let record: IdRecord = record?;
+            if record.internal == 0 {
+                return Err(Error::ReservedId {
+                    kind: record.kind,
+                    external: record.external,
+                });
+            }
+            match seen.entry((record.kind, record.internal)) {
+                Entry::Occupied(first) => {
+                    return Err(Error::DuplicateId {
+                        kind: record.kind,
+                        internal: record.internal,
+                        first: first.remove(),
+                        second: record.external,
+                    });
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(record.external.clone());
+                }
+            }
+            match record.kind {
+                IdKind::Client => {
+                    let client = ClientId(record.internal);
+                    map.last_client = map.last_client.max(record.internal);
+                    map.names.insert(client, &record.external);
+                    map.clients.insert(record.external, client);
+                }
+                IdKind::Tx => {
+                    map.last_tx = map.last_tx.max(record.internal);
+                    map.txs.insert(record.external, TxId(record.internal));
+                }
+            }
+        }
+        Ok(map)
+    }
+
+    pub fn save<W: io::Write>(&self, writer: W) -> csv::Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        let mut clients: Vec<_> = self.clients.iter().collect();
+        clients.sort_by_key(|(_, id)| **id);
+        for (external, id) in clients {
+            writer.serialize(IdRecord {
+                kind: IdKind::Client,
+                external: external.clone(),
+                internal: id.0,
+            })?;
+        }
+        let mut txs: Vec<_> = self.txs.iter().collect();
+        txs.sort_by_key(|(_, id)| **id);
+        for (external, id) in txs {
+            writer.serialize(IdRecord {
+                kind: IdKind::Tx,
+                external: external.clone(),
+                internal: id.0,
+            })?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn client(&mut self, external: &str) -> ClientId {
+        if let Some(id) = self.clients.get(external) {
+            return *id;
+        }
+        self.last_client += 1;
+        let id = ClientId(self.last_client);
+        self.clients.insert(external.to_string(), id);
+        self.names.insert(id, external);
+        id
+    }
+
+    /// The internal id of a partner client id seen before, without assigning one.
+    pub fn find_client(&self, external: &str) -> Option<ClientId> {
+        self.clients.get(external).copied()
+    }
+
+    /// A live view of the partner id behind each client id.
+    pub fn client_names(&self) -> ClientNames {
+        self.names.clone()
+    }
+
+    /// The internal id of a partner transaction id seen before, without assigning one.
+    pub fn find_tx(&self, external: &str) -> Option<TxId> {
+        self.txs.get(external).copied()
+    }
+
+    pub fn tx(&mut self, external: &str) -> TxId {
+        if let Some(id) = self.txs.get(external) {
+            return *id;
+        }
+        self.last_tx += 1;
+        let id = TxId(self.last_tx);
+        self.txs.insert(external.to_string(), id);
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_id_map_round_trip() {
+        // given ...
+        let mut map = IdMap::default();
+        let alice = map.client("alice");
+        let bob = map.client("bob");
+        let deposit = map.tx("DEP-1");
+        let mut saved = Vec::new();
+        map.save(&mut saved).unwrap();
+
+        // when ...
+        let mut loaded = IdMap::load(saved.as_slice()).unwrap();
+
+        // then ...
+        assert_eq!((alice, bob, deposit), (ClientId(1), ClientId(2), TxId(1)));
+        assert_eq!(loaded.client("bob"), bob);
+        assert_eq!(loaded.tx("DEP-1"), deposit);
+        assert_eq!(loaded.client("carol"), ClientId(3));
+    }
+
+    #[test]
+    fn test_client_names_follow_the_map() {
+        // given ...
+        let mut map = IdMap::default();
+        map.client("alice");
+        let names = map.client_names();
+
+        // when ...
+        let bob = map.client("bob");
+
+        // then ...
+        assert_eq!(names.get(ClientId(1)), Some(String::from("alice")));
+        assert_eq!(names.get(bob), Some(String::from("bob")));
+        assert_eq!(names.get(ClientId(3)), None);
+    }
+
+    #[test]
+    fn test_load_rejects_the_reserved_id() {
+        // given ...
+        let data = "kind,external,internal\n\
+        client,alice,1\n\
+        tx,DEP-1,0\n";
+
+        // when ...
+        let result = IdMap::load(data.as_bytes());
+
+        // then ...
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "tx \"DEP-1\" is mapped to the reserved id 0"
+        );
+    }
+
+    #[test]
+    fn test_load_rejects_shared_internal_ids() {
+        // given ...
+        let data = "kind,external,internal\n\
+        client,alice,1\n\
+        tx,DEP-1,1\n\
+        client,bob,1\n";
+
+        // when ...
+        let result = IdMap::load(data.as_bytes());
+
+        // then ...
+        let error = result.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "clients \"alice\" and \"bob\" are both mapped to 1"
+        );
+        assert_eq!(error.code(), ErrorCode::InvalidConfig);
+    }
+}
@@ -1,27 +1,199 @@
 use crate::account_state;
+use crate::account_state::{AccountState, AccountStatus};
 use crate::account_store::AccountStore;
+use crate::blocklist::Blocklist;
+use crate::dispute_policy::DisputePolicy;
+use crate::error::{Coded, ErrorCode};
+use crate::event::{Event, Observer, Observers};
+use crate::history::{History, PointInTime};
+use crate::id::{ClientId, ClientNames, TxId};
+use crate::lock_policy::LockPolicy;
+use crate::payout::Payout;
+use crate::risk_rules::{Action, Activity, RiskRules};
+use crate::stored_transaction::StoredTransaction;
 use crate::transaction::{
-    ChargebackTransaction, DepositTransaction, DisputeTransaction, ResolveTransaction, Transaction,
-    WithdrawalTransaction,
+    ChargebackTransaction, CloseTransaction, DepositTransaction, DisputeTransaction,
+    OpenTransaction, ResolveTransaction, Transaction, WithdrawalTransaction, rfc3339,
 };
-use crate::transaction_store::TransactionStore;
-use log::error;
+use crate::transaction_store::{Checkpoint, TransactionStore};
+use crate::transaction_type::TransactionType;
+use crate::verification::{VerificationReport, Violation};
+use chrono::{DateTime, TimeDelta, Utc};
+use log::{info, warn};
+use rust_decimal::Decimal;
+use std::collections::{BTreeSet, HashMap, HashSet, VecDeque};
+use std::io;
 use thiserror::Error;
+use tracing::instrument;
 
-#[derive(Debug, Error, PartialEq)]
+#[derive(Debug, Clone, Error, PartialEq)]
 pub enum Error {
     #[error("{0}")]
     AccountStateError(#[from] account_state::Error),
     #[error("Account ({client}) Dispute transaction {tx} not found")]
-    DisputeTransactionNotFound { client: u16, tx: u32 },
+    DisputeTransactionNotFound { client: ClientId, tx: TxId },
     #[error("Account ({client}) is attempting to dispute transaction {tx} owned by client {owner}")]
-    DisputeUnOwnedTransaction { client: u16, tx: u32, owner: u16 },
+    DisputeUnOwnedTransaction {
+        client: ClientId,
+        tx: TxId,
+        owner: ClientId,
+    },
+    #[error("Account ({client}) transaction {tx} has already been processed")]
+    DuplicateTransaction { client: ClientId, tx: TxId },
+    #[error("Account ({client}) is not open, transaction {tx} rejected")]
+    AccountNotOpen { client: ClientId, tx: TxId },
+    #[error("Account ({client}) is already open, transaction {tx} rejected")]
+    AccountAlreadyOpen { client: ClientId, tx: TxId },
+    #[error("Account ({client}) does not exist")]
+    AccountNotFound { client: ClientId },
+    #[error("Account ({client}) transaction {tx} would take deposits pending KYC above {cap}")]
+    KycDepositCapExceeded {
+        client: ClientId,
+        tx: TxId,
+        cap: Decimal,
+    },
+    #[error(
+        "Account ({client}) transaction {tx} at {} is earlier than a previous transaction at {}",
+        rfc3339(timestamp),
+        rfc3339(previous)
+    )]
+    OutOfOrder {
+        client: ClientId,
+        tx: TxId,
+        timestamp: DateTime<Utc>,
+        previous: DateTime<Utc>,
+    },
+    #[error("Account ({client}) transaction {tx} is no longer disputable")]
+    DisputeWindowExpired { client: ClientId, tx: TxId },
+    #[error("Account ({client}) transaction {tx} breaks the rule: {rule}")]
+    RuleViolation {
+        client: ClientId,
+        tx: TxId,
+        rule: String,
+    },
+    #[error(
+        "Account ({client}) is blocklisted, transaction {tx} rejected{}",
+        if note.is_empty() { String::new() } else { format!(": {note}") }
+    )]
+    ClientBlocked {
+        client: ClientId,
+        tx: TxId,
+        note: String,
+    },
+}
+
+impl Error {
+    pub fn client(&self) -> ClientId {
+        match self {
+            Error::AccountStateError(e) => e.client(),
+            Error::DisputeTransactionNotFound { client, .. }
+            | Error::DisputeUnOwnedTransaction { client, .. }
+            | Error::DuplicateTransaction { client, .. }
+            | Error::AccountNotOpen { client, .. }
+            | Error::AccountAlreadyOpen { client, .. }
+            | Error::AccountNotFound { client }
+            | Error::KycDepositCapExceeded { client, .. }
+            | Error::OutOfOrder { client, .. }
+            | Error::DisputeWindowExpired { client, .. }
+            | Error::RuleViolation { client, .. }
+            | Error::ClientBlocked { client, .. } => *client,
+        }
+    }
+
+    /// The transaction the error is about, when known.
+    pub fn tx(&self) -> Option<TxId> {
+        match self {
+            Error::AccountStateError(e) => e.tx(),
+            Error::DisputeTransactionNotFound { tx, .. }
+            | Error::DisputeUnOwnedTransaction { tx, .. }
+            | Error::DuplicateTransaction { tx, .. }
+            | Error::AccountNotOpen { tx, .. }
+            | Error::AccountAlreadyOpen { tx, .. }
+            | Error::KycDepositCapExceeded { tx, .. }
+            | Error::OutOfOrder { tx, .. }
+            | Error::DisputeWindowExpired { tx, .. }
+            | Error::RuleViolation { tx, .. }
+            | Error::ClientBlocked { tx, .. } => Some(*tx),
+            Error::AccountNotFound { .. } => None,
+        }
+    }
+}
+
+impl Coded for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::AccountStateError(e) => e.code(),
+            Error::DisputeTransactionNotFound { .. } => ErrorCode::DisputeTransactionNotFound,
+            Error::DisputeUnOwnedTransaction { .. } => ErrorCode::DisputeUnownedTransaction,
+            Error::DuplicateTransaction { .. } => ErrorCode::DuplicateTransaction,
+            Error::AccountNotOpen { .. } => ErrorCode::AccountNotOpen,
+            Error::AccountAlreadyOpen { .. } => ErrorCode::AccountAlreadyOpen,
+            Error::AccountNotFound { .. } => ErrorCode::AccountNotFound,
+            Error::KycDepositCapExceeded { .. } => ErrorCode::KycDepositCap,
+            Error::OutOfOrder { .. } => ErrorCode::OutOfOrder,
+            Error::DisputeWindowExpired { .. } => ErrorCode::DisputeWindowExpired,
+            Error::RuleViolation { .. } => ErrorCode::RuleViolation,
+            Error::ClientBlocked { .. } => ErrorCode::ClientBlocked,
+        }
+    }
+}
+
+/// The member of a batch that failed, by position, and why.
+#[derive(Debug, Error, PartialEq)]
+#[error("{source}")]
+pub struct BatchError {
+    pub index: usize,
+    pub source: Error,
+}
+
+/// What a batch restores for each client it touches when it is rolled back.
+struct Snapshot {
+    account: Option<AccountState>,
+    history: usize,
+    activity: Option<VecDeque<Activity>>,
+}
+
+/// Everything a batch restores when it is rolled back.
+struct BatchSnapshot {
+    clients: HashMap<ClientId, Snapshot>,
+    /// The open dispute, if any, of every transaction a member disputes, resolves or
+    /// charges back.
+    disputes: HashMap<TxId, Option<(ClientId, DateTime<Utc>)>>,
+    payouts: usize,
+    transactions: Checkpoint,
+    /// The stored transaction, if any, under the tx id of every deposit and withdrawal.
+    stored: HashMap<TxId, Option<StoredTransaction>>,
 }
 
 #[derive(Debug, Default)]
 pub struct Ledger {
     accounts: AccountStore,
     transactions: TransactionStore,
+    /// Ids of applied transactions that only change an account's status, which the
+    /// transaction store does not keep, so no later transaction reuses them.
+    status_txs: HashSet<TxId>,
+    verify_each: bool,
+    /// The first failed check since [`Ledger::take_violations`] was last called.
+    violations: Option<VerificationReport>,
+    require_open: bool,
+    kyc_deposit_cap: Option<Decimal>,
+    timestamp_tolerance: TimeDelta,
+    dispute_policy: DisputePolicy,
+    /// When each open dispute was opened, for automatic resolution. Disputes are also
+    /// queued by opening time; stale queue entries are skipped when they come up.
+    dispute_opened: HashMap<TxId, (ClientId, DateTime<Utc>)>,
+    dispute_queue: BTreeSet<(DateTime<Utc>, TxId)>,
+    history: Option<History>,
+    risk_rules: RiskRules,
+    lock_policy: LockPolicy,
+    blocklist: Blocklist,
+    /// Each client's recent deposits and withdrawals, as far back as the rules look.
+    activity: HashMap<ClientId, VecDeque<Activity>>,
+    observers: Observers,
+    /// Events not yet published; held back while a batch is in progress.
+    pending: Vec<Event>,
+    batching: bool,
+    payouts: Vec<Payout>,
 }
 
 impl Ledger {
@@ -29,35 +201,702 @@ impl Ledger {
         Ledger {
             accounts,
             transactions,
+            status_txs: HashSet::new(),
+            verify_each: false,
+            violations: None,
+            require_open: false,
+            kyc_deposit_cap: None,
+            timestamp_tolerance: TimeDelta::zero(),
+            dispute_policy: DisputePolicy::default(),
+            dispute_opened: HashMap::new(),
+            dispute_queue: BTreeSet::new(),
+            history: None,
+            risk_rules: RiskRules::default(),
+            lock_policy: LockPolicy::default(),
+            blocklist: Blocklist::default(),
+            activity: HashMap::new(),
+            observers: Observers::default(),
+            pending: Vec::new(),
+            batching: false,
+            payouts: Vec::new(),
+        }
+    }
+
+    /// Runs [`Ledger::verify`] after every processed transaction and keeps the first
+    /// failed report for [`Ledger::take_violations`]. Intended for debugging, since every
+    /// check walks the whole ledger.
+    pub fn with_verify_each(mut self, verify_each: bool) -> Self {
+        self.verify_each = verify_each;
+        self
+    }
+
+    pub fn take_violations(&mut self) -> Option<VerificationReport> {
+        self.violations.take()
+    }
+
+    /// Rejects deposits and withdrawals for clients without an account instead of opening
+    /// one for them, so accounts only come from `open` transactions.
+    pub fn with_require_open(mut self, require_open: bool) -> Self {
+        self.require_open = require_open;
+        self
+    }
+
+    /// Opens new accounts, whether by an `open` transaction or implicitly, pending KYC,
+    /// accepting at most `cap` in deposits until an `open` for the client records the KYC
+    /// approval.
+    pub fn with_kyc_deposit_cap(mut self, cap: Decimal) -> Self {
+        self.kyc_deposit_cap = Some(cap);
+        self
+    }
+
+    /// How far a timestamped transaction may fall behind the latest one already applied
+    /// to the same client before it is rejected as out of order. Defaults to zero.
+    pub fn with_timestamp_tolerance(mut self, tolerance: TimeDelta) -> Self {
+        self.timestamp_tolerance = tolerance;
+        self
+    }
+
+    pub fn with_dispute_policy(mut self, policy: DisputePolicy) -> Self {
+        self.dispute_policy = policy;
+        self
+    }
+
+    /// Checks every deposit and withdrawal against `rules` before it touches the account.
+    pub fn with_risk_rules(mut self, rules: RiskRules) -> Self {
+        self.risk_rules = rules;
+        self
+    }
+
+    /// Locks accounts that break one of the policy's limits after a transaction is applied.
+    pub fn with_lock_policy(mut self, policy: LockPolicy) -> Self {
+        self.lock_policy = policy;
+        self
+    }
+
+    /// Rejects every transaction of a listed client before it reaches the account store.
+    /// A watched list is refreshed as transactions are processed.
+    pub fn with_blocklist(mut self, blocklist: Blocklist) -> Self {
+        self.blocklist = blocklist;
+        self
+    }
+
+    /// Matches the blocklist against the partner ids behind each client.
+    pub(crate) fn set_client_names(&mut self, names: ClientNames) {
+        self.blocklist = std::mem::take(&mut self.blocklist).with_client_names(names);
+    }
+
+    /// Keeps every applied transaction so [`Ledger::account_at`] can answer point-in-time
+    /// queries. Costs memory proportional to the number of applied transactions.
+    pub fn with_history(mut self, history: bool) -> Self {
+        self.history = history.then(History::default);
+        self
+    }
+
+    /// Notifies `observer` of every event the ledger publishes, after the observers
+    /// registered before it.
+    pub fn with_observer(mut self, observer: impl Observer + Send + 'static) -> Self {
+        self.observers.push(Box::new(observer));
+        self
+    }
+
+    /// Flushes the registered observers, returning the first failure to write an event.
+    pub fn flush_observers(&mut self) -> io::Result<()> {
+        self.observers.flush()
+    }
+
+    /// The client's account as it was at `at`, rebuilt from the recorded history without
+    /// touching the current state. `None` without [`Ledger::with_history`], or when the
+    /// client had no applied transactions by then.
+    pub fn account_at(&self, client: ClientId, at: PointInTime) -> Option<AccountState> {
+        self.history.as_ref()?.account_at(client, at)
+    }
+
+    /// The history kept by [`Ledger::with_history`], if any.
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    pub fn accounts(&self) -> &AccountStore {
+        &self.accounts
+    }
+
+    /// The payouts of every closed account, in the order they were closed.
+    pub fn payouts(&self) -> &[Payout] {
+        &self.payouts
+    }
+
+    /// Stops deposits, withdrawals and closing for the client until an `open` transaction
+    /// activates the account again. Disputes on a frozen account still proceed.
+    pub fn freeze(&mut self, client: ClientId) -> Result<(), Error> {
+        let account = self
+            .accounts
+            .get_mut(client)
+            .ok_or(Error::AccountNotFound { client })?;
+        account.freeze()?;
+        if let Some(history) = self.history.as_mut() {
+            history.record_freeze(client);
+        }
+        info!("Account ({client}) was frozen");
+        self.emit(Event::AccountFrozen { client });
+        if !self.batching {
+            self.publish();
         }
+        Ok(())
     }
 
+    #[instrument(
+        level = "debug",
+        skip_all,
+        fields(
+            tx = %transaction.tx(),
+            client = %transaction.client(),
+            r#type = transaction.r#type().name()
+        )
+    )]
     pub fn process(&mut self, transaction: &Transaction) -> Result<(), Error> {
+        let was_locked = self
+            .accounts
+            .get(transaction.client())
+            .is_some_and(AccountState::locked);
+        let result = self
+            .check_blocklist(transaction)
+            .and_then(|()| self.check_timestamp(transaction));
+        let result = result.and_then(|()| match transaction {
+            Transaction::Deposit(deposit) => self.process_deposit(deposit),
+            Transaction::Withdrawal(withdrawal) => self.process_withdrawal(withdrawal),
+            Transaction::Dispute(dispute) => self.process_dispute(dispute),
+            Transaction::Resolve(resolve) => self.process_resolve(resolve),
+            Transaction::Chargeback(chargeback) => self.process_chargeback(chargeback),
+            Transaction::Open(open) => self.process_open(open),
+            Transaction::Close(close) => self.process_close(close),
+        });
+        if result.is_ok() {
+            if let Some(timestamp) = transaction.timestamp()
+                && let Some(account) = self.accounts.get_mut(transaction.client())
+            {
+                account.observe_timestamp(timestamp);
+            }
+            if let Some(history) = self.history.as_mut() {
+                history.record(transaction.clone());
+            }
+            self.emit_applied(transaction);
+            self.enforce_lock_policy(transaction.client());
+            if !was_locked {
+                self.emit_locked(transaction.client());
+            }
+            // Only accepted rows move the dispute clock, and inside a batch only once the
+            // batch stands.
+            if !self.batching
+                && let Some(timestamp) = transaction.timestamp()
+            {
+                self.auto_resolve(timestamp);
+            }
+        } else if let Err(reason) = &result {
+            self.emit(Event::TransactionRejected {
+                client: transaction.client(),
+                tx: transaction.tx(),
+                code: reason.code(),
+                reason: reason.clone(),
+            });
+        }
+        if !self.batching {
+            self.publish();
+        }
+        if self.verify_each
+            && self.violations.is_none()
+            && let Err(report) = self.verify()
+        {
+            self.violations = Some(report);
+        }
+        result
+    }
+
+    /// Applies the transactions all-or-nothing: if any member is rejected, every change
+    /// made by the members before it is rolled back. Disputes due for automatic resolution
+    /// are resolved as of the latest member timestamp once the whole batch is applied.
+    #[instrument(level = "debug", skip_all, fields(size = transactions.len()))]
+    pub fn process_batch(&mut self, transactions: &[Transaction]) -> Result<(), BatchError> {
+        let mut snapshot = BatchSnapshot {
+            clients: HashMap::new(),
+            disputes: HashMap::new(),
+            payouts: self.payouts.len(),
+            transactions: self.transactions.checkpoint(),
+            stored: HashMap::new(),
+        };
+        for transaction in transactions {
+            let client = transaction.client();
+            snapshot.clients.entry(client).or_insert_with(|| Snapshot {
+                account: self.accounts.get(client).cloned(),
+                history: self
+                    .history
+                    .as_ref()
+                    .map_or(0, |history| history.len(client)),
+                activity: self.activity.get(&client).cloned(),
+            });
+            if let Transaction::Deposit(_) | Transaction::Withdrawal(_) = transaction {
+                let tx = transaction.tx();
+                snapshot
+                    .stored
+                    .entry(tx)
+                    .or_insert_with(|| self.transactions.get(tx).cloned());
+            }
+            if let Transaction::Dispute(_) | Transaction::Resolve(_) | Transaction::Chargeback(_) =
+                transaction
+            {
+                let tx = transaction.tx();
+                snapshot
+                    .disputes
+                    .entry(tx)
+                    .or_insert_with(|| self.dispute_opened.get(&tx).copied());
+            }
+        }
+        let events = self.pending.len();
+        self.batching = true;
+        let mut result = Ok(());
+        for (index, transaction) in transactions.iter().enumerate() {
+            if let Err(source) = self.process(transaction) {
+                self.rollback(&transactions[..index], snapshot);
+                // Only the rejection survives; the events of the rolled back members
+                // never happened, but anything pending from before the batch still did.
+                let rejected = self.pending.pop();
+                self.pending.truncate(events);
+                self.pending.extend(rejected);
+                result = Err(BatchError { index, source });
+                break;
+            }
+        }
+        self.batching = false;
+        if result.is_ok()
+            && let Some(now) = transactions.iter().filter_map(Transaction::timestamp).max()
+        {
+            self.auto_resolve(now);
+        }
+        self.publish();
+        result
+    }
+
+    /// Locks the account if it breaks the lock policy. An account just locked by a
+    /// chargeback is still evaluated, so the chargeback counts towards the ratio and
+    /// the reason is recorded; one already locked by the policy is left alone.
+    fn enforce_lock_policy(&mut self, client: ClientId) {
+        if self.lock_policy.is_empty() {
+            return;
+        }
+        let Some(account) = self.accounts.get_mut(client).filter(|account| {
+            account.status() != AccountStatus::Closed && account.lock_reason().is_none()
+        }) else {
+            return;
+        };
+        if let Some(reason) = self.lock_policy.evaluate(account) {
+            info!("Account ({client}) was locked: {reason}");
+            account.lock(reason.clone());
+            if let Some(history) = self.history.as_mut() {
+                history.record_lock(client, reason);
+            }
+        }
+    }
+
+    /// Emits the lock of an account that was not locked before the transaction, with the
+    /// policy's reason if the policy locked it.
+    fn emit_locked(&mut self, client: ClientId) {
+        if let Some(account) = self.accounts.get(client).filter(|account| account.locked()) {
+            let reason = account.lock_reason().cloned();
+            self.emit(Event::AccountLocked { client, reason });
+        }
+    }
+
+    fn emit(&mut self, event: Event) {
+        if !self.observers.is_empty() {
+            self.pending.push(event);
+        }
+    }
+
+    fn publish(&mut self) {
+        let pending = std::mem::take(&mut self.pending);
+        self.observers.publish(pending);
+    }
+
+    /// Emits what an applied transaction changed.
+    fn emit_applied(&mut self, transaction: &Transaction) {
+        if self.observers.is_empty() {
+            return;
+        }
+        let client = transaction.client();
+        let tx = transaction.tx();
+        let amount = self
+            .transactions
+            .get(tx)
+            .map_or(Decimal::ZERO, StoredTransaction::amount);
         match transaction {
-            Transaction::Deposit(deposit) => self.process_deposit(&deposit),
-            Transaction::Withdrawal(withdrawal) => self.process_withdrawal(&withdrawal),
-            Transaction::Dispute(dispute) => self.process_dispute(&dispute),
-            Transaction::Resolve(resolve) => self.process_resolve(&resolve),
-            Transaction::Chargeback(chargeback) => self.process_chargeback(&chargeback),
+            Transaction::Deposit(_) => self.emit(Event::FundsDeposited { client, tx, amount }),
+            Transaction::Withdrawal(_) => self.emit(Event::FundsWithdrawn { client, tx, amount }),
+            Transaction::Dispute(_) => self.emit(Event::FundsHeld { client, tx, amount }),
+            Transaction::Resolve(_) => self.emit(Event::DisputeResolved {
+                client,
+                tx,
+                amount,
+                automatic: false,
+            }),
+            Transaction::Chargeback(_) => self.emit(Event::FundsChargedBack { client, tx, amount }),
+            Transaction::Open(_) => {
+                if let Some(account) = self.accounts.get(client) {
+                    let status = account.status();
+                    self.emit(Event::AccountOpened { client, tx, status });
+                }
+            }
+            Transaction::Close(_) => {
+                if let Some(payout) = self.payouts.last() {
+                    let amount = payout.amount;
+                    self.emit(Event::AccountClosed { client, tx, amount });
+                }
+            }
+        }
+    }
+
+    fn rollback(&mut self, applied: &[Transaction], snapshot: BatchSnapshot) {
+        self.payouts.truncate(snapshot.payouts);
+        self.transactions.truncate(snapshot.transactions);
+        for (tx, stored) in snapshot.stored {
+            self.transactions.restore(tx, stored);
+        }
+        for transaction in applied {
+            match transaction {
+                Transaction::Open(_) | Transaction::Close(_) => {
+                    self.status_txs.remove(&transaction.tx());
+                }
+                Transaction::Deposit(_)
+                | Transaction::Withdrawal(_)
+                | Transaction::Dispute(_)
+                | Transaction::Resolve(_)
+                | Transaction::Chargeback(_) => {}
+            }
+        }
+        for (tx, opened) in snapshot.disputes {
+            let current = match opened {
+                Some(opened) => self.dispute_opened.insert(tx, opened),
+                None => self.dispute_opened.remove(&tx),
+            };
+            // Drop the queue entry of a dispute the batch opened.
+            if let Some((_, at)) = current
+                && opened.is_none_or(|(_, opened)| opened != at)
+            {
+                self.dispute_queue.remove(&(at, tx));
+            }
+        }
+        for (client, snapshot) in snapshot.clients {
+            if let Some(history) = self.history.as_mut() {
+                history.truncate(client, snapshot.history);
+            }
+            match snapshot.activity {
+                Some(activity) => self.activity.insert(client, activity),
+                None => self.activity.remove(&client),
+            };
+            match snapshot.account {
+                Some(account) => self.accounts.restore(account),
+                None => self.accounts.remove(client),
+            }
+        }
+    }
+
+    /// Checks every account invariant plus the ledger-wide conservation of funds:
+    /// the sum of account totals must equal deposits minus withdrawals, chargebacks and
+    /// payouts.
+    pub fn verify(&self) -> Result<(), VerificationReport> {
+        let mut report = VerificationReport::default();
+        let mut accounts: Vec<_> = self.accounts.iter().collect();
+        accounts.sort_by_key(|account| account.client());
+        let mut balances = Some(Decimal::ZERO);
+        for account in accounts {
+            report.extend(account.violations());
+            balances = balances.and_then(|sum| sum.checked_add(account.total()));
+        }
+
+        let mut expected = Some(Decimal::ZERO);
+        for stored in self.transactions.iter() {
+            expected = expected.and_then(|sum| match stored {
+                StoredTransaction::Deposit(deposit) => sum.checked_add(deposit.amount),
+                StoredTransaction::Withdrawal(withdrawal) => sum.checked_sub(withdrawal.amount),
+            });
+        }
+        for amount in self.transactions.chargebacks() {
+            expected = expected.and_then(|sum| sum.checked_sub(*amount));
+        }
+        for payout in &self.payouts {
+            expected = expected.and_then(|sum| sum.checked_sub(payout.amount));
+        }
+        match (balances, expected) {
+            (Some(balances), Some(expected)) if balances != expected => {
+                report.push(Violation::BalanceMismatch { balances, expected });
+            }
+            (Some(_), Some(_)) => {}
+            _ => report.push(Violation::BalanceOverflow),
+        }
+
+        if report.is_clean() {
+            Ok(())
+        } else {
+            Err(report)
         }
     }
 
     fn process_deposit(&mut self, deposit: &DepositTransaction) -> Result<(), Error> {
-        let account = self.accounts.get_or_create(deposit.client);
-        account.deposit(deposit.amount)?;
+        let activity = Activity {
+            r#type: TransactionType::Deposit,
+            amount: deposit.amount,
+            timestamp: deposit.timestamp,
+        };
+        self.check_kyc_deposit_cap(deposit)?;
+        let flagged = self.check_rules(deposit.client, deposit.tx, &activity)?;
+        self.apply_to_account(deposit.client, deposit.tx, |account| {
+            account.deposit(deposit.amount)
+        })?;
         self.transactions.store(deposit);
+        self.record_activity(deposit.client, deposit.tx, activity, flagged);
         Ok(())
     }
 
     fn process_withdrawal(&mut self, withdrawal: &WithdrawalTransaction) -> Result<(), Error> {
-        let account = self.accounts.get_or_create(withdrawal.client);
-        account.withdraw(withdrawal.amount)?;
+        let activity = Activity {
+            r#type: TransactionType::Withdrawal,
+            amount: withdrawal.amount,
+            timestamp: withdrawal.timestamp,
+        };
+        let flagged = self.check_rules(withdrawal.client, withdrawal.tx, &activity)?;
+        self.apply_to_account(withdrawal.client, withdrawal.tx, |account| {
+            account.withdraw(withdrawal.amount)
+        })?;
         self.transactions.store(withdrawal);
+        self.record_activity(withdrawal.client, withdrawal.tx, activity, flagged);
+        Ok(())
+    }
+
+    /// Applies a deposit or withdrawal to the client's account. Without an account, one is
+    /// opened unless [`Ledger::with_require_open`] is set, but only kept if `apply`
+    /// succeeds.
+    fn apply_to_account(
+        &mut self,
+        client: ClientId,
+        tx: TxId,
+        apply: impl FnOnce(&mut AccountState) -> Result<(), account_state::Error>,
+    ) -> Result<(), Error> {
+        if let Some(account) = self.accounts.get_mut(client) {
+            return Ok(apply(account)?);
+        }
+        if self.require_open {
+            return Err(Error::AccountNotOpen { client, tx });
+        }
+        let mut account = self.new_account(client);
+        apply(&mut account)?;
+        self.accounts.insert(account);
+        Ok(())
+    }
+
+    /// A new account for the client, pending KYC when there is a deposit cap.
+    fn new_account(&self, client: ClientId) -> AccountState {
+        match self.kyc_deposit_cap {
+            Some(_) => AccountState::pending_kyc(client),
+            None => AccountState::new(client),
+        }
+    }
+
+    /// Opens a new account, pending KYC when there is a deposit cap, or activates a
+    /// pending or frozen one.
+    fn process_open(&mut self, open: &OpenTransaction) -> Result<(), Error> {
+        self.check_unique(open.client, open.tx)?;
+        match self.accounts.get_mut(open.client) {
+            None => self.accounts.insert(self.new_account(open.client)),
+            Some(account) if account.status() == AccountStatus::Active => {
+                return Err(Error::AccountAlreadyOpen {
+                    client: open.client,
+                    tx: open.tx,
+                });
+            }
+            Some(account) => account.activate()?,
+        }
+        self.status_txs.insert(open.tx);
         Ok(())
     }
 
+    fn process_close(&mut self, close: &CloseTransaction) -> Result<(), Error> {
+        self.check_unique(close.client, close.tx)?;
+        let account = self
+            .accounts
+            .get_mut(close.client)
+            .ok_or(Error::AccountNotOpen {
+                client: close.client,
+                tx: close.tx,
+            })?;
+        let amount = account.close()?;
+        self.payouts.push(Payout {
+            client: close.client,
+            tx: close.tx,
+            amount,
+        });
+        self.status_txs.insert(close.tx);
+        Ok(())
+    }
+
+    fn check_kyc_deposit_cap(&self, deposit: &DepositTransaction) -> Result<(), Error> {
+        let Some(cap) = self.kyc_deposit_cap else {
+            return Ok(());
+        };
+        let deposited = match self.accounts.get(deposit.client) {
+            Some(account) if account.status() == AccountStatus::PendingKyc => account.deposited(),
+            Some(_) => return Ok(()),
+            // The deposit opens the account implicitly, pending KYC.
+            None if !self.require_open => Decimal::ZERO,
+            None => return Ok(()),
+        };
+        if deposited
+            .checked_add(deposit.amount)
+            .is_none_or(|deposited| deposited > cap)
+        {
+            return Err(Error::KycDepositCapExceeded {
+                client: deposit.client,
+                tx: deposit.tx,
+                cap,
+            });
+        }
+        Ok(())
+    }
+
+    /// Rejects the transaction if it breaks a rejecting rule, otherwise returns the
+    /// flagging rules it breaks.
+    fn check_rules(
+        &self,
+        client: ClientId,
+        tx: TxId,
+        activity: &Activity,
+    ) -> Result<Vec<String>, Error> {
+        if self.risk_rules.is_empty() {
+            return Ok(Vec::new());
+        }
+        let violations = self
+            .risk_rules
+            .violations(self.activity.get(&client), activity);
+        if let Some((rule, _)) = violations
+            .iter()
+            .find(|(_, action)| *action == Action::Reject)
+        {
+            return Err(Error::RuleViolation {
+                client,
+                tx,
+                rule: rule.to_string(),
+            });
+        }
+        Ok(violations
+            .iter()
+            .map(|(rule, _)| rule.to_string())
+            .collect())
+    }
+
+    fn record_activity(
+        &mut self,
+        client: ClientId,
+        tx: TxId,
+        activity: Activity,
+        flagged: Vec<String>,
+    ) {
+        if self.risk_rules.is_empty() {
+            return;
+        }
+        self.risk_rules
+            .record(self.activity.entry(client).or_default(), activity);
+        for rule in flagged {
+            warn!("Account ({client}) transaction {tx} was flagged for review: {rule}");
+            self.emit(Event::TransactionFlagged { client, tx, rule });
+        }
+    }
+
+    fn check_blocklist(&mut self, transaction: &Transaction) -> Result<(), Error> {
+        if self.blocklist.is_empty() && !self.blocklist.is_watched() {
+            return Ok(());
+        }
+        self.blocklist.refresh();
+        match self.blocklist.get(transaction.client()) {
+            Some(note) => Err(Error::ClientBlocked {
+                client: transaction.client(),
+                tx: transaction.tx(),
+                note: note.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn check_timestamp(&self, transaction: &Transaction) -> Result<(), Error> {
+        let client = transaction.client();
+        let previous = self
+            .accounts
+            .get(client)
+            .and_then(|account| account.last_timestamp());
+        match (transaction.timestamp(), previous) {
+            (Some(timestamp), Some(previous))
+                if timestamp + self.timestamp_tolerance < previous =>
+            {
+                Err(Error::OutOfOrder {
+                    client,
+                    tx: transaction.tx(),
+                    timestamp,
+                    previous,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Resolves every dispute that has been open longer than the policy allows as of `now`.
+    fn auto_resolve(&mut self, now: DateTime<Utc>) {
+        let Some(after) = self.dispute_policy.auto_resolve_after() else {
+            return;
+        };
+        while let Some(&(opened, tx)) = self.dispute_queue.first()
+            && opened + after <= now
+        {
+            self.dispute_queue.pop_first();
+            if self.dispute_opened.get(&tx).map(|&(_, current)| current) != Some(opened) {
+                continue;
+            }
+            let Some((client, _)) = self.dispute_opened.remove(&tx) else {
+                continue;
+            };
+            if let Some(account) = self.accounts.get_mut(client)
+                && account.resolve(tx).is_ok()
+            {
+                info!("Account ({client}) dispute of transaction {tx} was resolved automatically");
+                let amount = self
+                    .transactions
+                    .get(tx)
+                    .map_or(Decimal::ZERO, StoredTransaction::amount);
+                self.emit(Event::DisputeResolved {
+                    client,
+                    tx,
+                    amount,
+                    automatic: true,
+                });
+                if let Some(history) = self.history.as_mut() {
+                    history.record(Transaction::Resolve(ResolveTransaction {
+                        client,
+                        tx,
+                        timestamp: Some(now),
+                    }));
+                }
+            }
+        }
+    }
+
+    fn check_unique(&self, client: ClientId, tx: TxId) -> Result<(), Error> {
+        if self.transactions.get(tx).is_some() || self.status_txs.contains(&tx) {
+            Err(Error::DuplicateTransaction { client, tx })
+        } else {
+            Ok(())
+        }
+    }
+
     fn process_dispute(&mut self, dispute: &DisputeTransaction) -> Result<(), Error> {
-        let account = self.accounts.get_or_create(dispute.client);
+        let not_found = Error::DisputeTransactionNotFound {
+            client: dispute.client,
+            tx: dispute.tx,
+        };
         if let Some(disputed) = self.transactions.get(dispute.tx) {
             if dispute.client != disputed.client() {
                 Err(Error::DisputeUnOwnedTransaction {
@@ -66,40 +905,1113 @@ impl Ledger {
                     owner: disputed.client(),
                 })
             } else {
+                let window = self
+                    .dispute_policy
+                    .window(dispute.client, disputed.r#type());
+                if let (Some(window), Some(opened), Some(original)) =
+                    (window, dispute.timestamp, disputed.timestamp())
+                    && opened - original > window
+                {
+                    return Err(Error::DisputeWindowExpired {
+                        client: dispute.client,
+                        tx: dispute.tx,
+                    });
+                }
+                let account = self.accounts.get_mut(dispute.client).ok_or(not_found)?;
                 account.dispute(disputed)?;
+                if let Some(opened) = dispute.timestamp {
+                    self.dispute_opened
+                        .insert(dispute.tx, (dispute.client, opened));
+                    self.dispute_queue.insert((opened, dispute.tx));
+                }
                 Ok(())
             }
         } else {
-            Err(Error::DisputeTransactionNotFound {
-                client: dispute.client,
-                tx: dispute.tx,
-            })
+            Err(not_found)
         }
     }
 
     fn process_resolve(&mut self, resolve: &ResolveTransaction) -> Result<(), Error> {
-        let account = self.accounts.get_or_create(resolve.client);
+        let account =
+            self.accounts
+                .get_mut(resolve.client)
+                .ok_or(account_state::Error::DisputeNotFound {
+                    client: resolve.client,
+                    tx: resolve.tx,
+                })?;
         account.resolve(resolve.tx)?;
+        self.dispute_opened.remove(&resolve.tx);
         Ok(())
     }
 
     fn process_chargeback(&mut self, chargeback: &ChargebackTransaction) -> Result<(), Error> {
-        let account = self.accounts.get_or_create(chargeback.client);
+        let account = self.accounts.get_mut(chargeback.client).ok_or(
+            account_state::Error::DisputeNotFound {
+                client: chargeback.client,
+                tx: chargeback.tx,
+            },
+        )?;
+        let amount = account.disputed_amount(chargeback.tx);
         account.chargeback(chargeback.tx)?;
+        self.dispute_opened.remove(&chargeback.tx);
+        self.transactions
+            .record_chargeback(amount.unwrap_or(Decimal::ZERO));
         Ok(())
     }
 
-    pub fn write_accounts<W: std::io::Write>(&self, writer: W) -> anyhow::Result<()>
+    #[instrument(level = "info", skip_all, fields(accounts = self.accounts.len()))]
+    pub fn write_accounts<W>(&self, writer: W) -> anyhow::Result<()>
     where
         W: std::io::Write,
     {
+        // The header is written up front so that an empty ledger still has one.
         let mut csv_writer = csv::WriterBuilder::new()
-            .has_headers(true)
+            .has_headers(false)
             .from_writer(writer);
+        csv_writer.write_record(AccountState::FIELDS)?;
         for account in self.accounts.iter() {
             csv_writer.serialize(account)?;
         }
         csv_writer.flush()?;
         Ok(())
     }
+
+    #[instrument(level = "info", skip_all, fields(accounts = self.accounts.len()))]
+    pub fn write_accounts_json<W>(&self, writer: W) -> anyhow::Result<()>
+    where
+        W: std::io::Write,
+    {
+        let accounts: Vec<_> = self.accounts.iter().collect();
+        serde_json::to_writer_pretty(writer, &accounts)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Error;
+    use super::*;
+    use crate::lock_policy::LockReason;
+    use crate::risk_rules::{Rule, Window};
+    use crate::transaction::*;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_verify_after_chargeback() {
+        // given ...
+        let mut ledger = Ledger::default();
+        let transactions = vec![
+            Transaction::Deposit(DepositTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                amount: Decimal::new(100, 0),
+                timestamp: None,
+            }),
+            Transaction::Deposit(DepositTransaction {
+                client: ClientId(2),
+                tx: TxId(2),
+                amount: Decimal::new(50, 0),
+                timestamp: None,
+            }),
+            Transaction::Withdrawal(WithdrawalTransaction {
+                client: ClientId(2),
+                tx: TxId(3),
+                amount: Decimal::new(20, 0),
+                timestamp: None,
+            }),
+            Transaction::Dispute(DisputeTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                timestamp: None,
+            }),
+            Transaction::Chargeback(ChargebackTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                timestamp: None,
+            }),
+        ];
+        for transaction in &transactions {
+            ledger.process(transaction).unwrap();
+        }
+
+        // when ...
+        let result = ledger.verify();
+
+        // then ...
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn test_verify_after_reused_tx() {
+        // given ...
+        let mut ledger = Ledger::default();
+        let transactions = vec![
+            Transaction::Deposit(DepositTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                amount: Decimal::new(100, 0),
+                timestamp: None,
+            }),
+            Transaction::Dispute(DisputeTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                timestamp: None,
+            }),
+            Transaction::Deposit(DepositTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                amount: Decimal::new(50, 0),
+                timestamp: None,
+            }),
+            Transaction::Chargeback(ChargebackTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                timestamp: None,
+            }),
+        ];
+        for transaction in &transactions {
+            ledger.process(transaction).unwrap();
+        }
+
+        // when ...
+        let result = ledger.verify();
+
+        // then ...
+        assert_eq!(result, Ok(()));
+        assert_eq!(
+            ledger.accounts.get(ClientId(1)).unwrap().total(),
+            Decimal::new(50, 0)
+        );
+    }
+
+    #[test]
+    fn test_verify_reports_negative_available() {
+        // given ...
+        let mut ledger = Ledger::default();
+        let transactions = vec![
+            Transaction::Deposit(DepositTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                amount: Decimal::new(100, 0),
+                timestamp: None,
+            }),
+            Transaction::Dispute(DisputeTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                timestamp: None,
+            }),
+            Transaction::Withdrawal(WithdrawalTransaction {
+                client: ClientId(1),
+                tx: TxId(2),
+                amount: Decimal::new(100, 0),
+                timestamp: None,
+            }),
+        ];
+        for transaction in &transactions {
+            ledger.process(transaction).unwrap();
+        }
+
+        // when ...
+        let result = ledger.verify();
+
+        // then ...
+        let report = result.unwrap_err();
+        assert_eq!(
+            report.violations(),
+            &[Violation::NegativeAvailable {
+                client: ClientId(1),
+                available: Decimal::new(-100, 0),
+            }]
+        );
+        assert_eq!(
+            report.to_string(),
+            "1 ledger invariant violation(s)\n  - Account (1) has negative available funds -100"
+        );
+    }
+
+    #[test]
+    fn test_process_batch_rolls_back_on_rejection() {
+        // given ...
+        let mut ledger = Ledger::default();
+        ledger
+            .process(&Transaction::Deposit(DepositTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                amount: Decimal::new(10, 0),
+                timestamp: None,
+            }))
+            .unwrap();
+        let batch = vec![
+            Transaction::Deposit(DepositTransaction {
+                client: ClientId(1),
+                tx: TxId(2),
+                amount: Decimal::new(100, 0),
+                timestamp: None,
+            }),
+            Transaction::Dispute(DisputeTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                timestamp: None,
+            }),
+            Transaction::Deposit(DepositTransaction {
+                client: ClientId(2),
+                tx: TxId(3),
+                amount: Decimal::new(5, 0),
+                timestamp: None,
+            }),
+            Transaction::Withdrawal(WithdrawalTransaction {
+                client: ClientId(1),
+                tx: TxId(4),
+                amount: Decimal::new(500, 0),
+                timestamp: None,
+            }),
+        ];
+
+        // when ...
+        let result = ledger.process_batch(&batch);
+
+        // then ...
+        assert_eq!(
+            result,
+            Err(BatchError {
+                index: 3,
+                source: Error::AccountStateError(account_state::Error::InsufficientFunds {
+                    client: ClientId(1)
+                }),
+            })
+        );
+        let account = ledger.accounts().get(ClientId(1)).unwrap();
+        assert_eq!(account.available(), Decimal::new(10, 0));
+        assert_eq!(account.held(), Decimal::ZERO);
+        assert!(ledger.accounts().get(ClientId(2)).is_none());
+        assert!(ledger.transactions.get(TxId(2)).is_none());
+        assert_eq!(ledger.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_process_batch_restores_reused_tx_on_rejection() {
+        // given ...
+        let mut ledger = Ledger::default();
+        ledger
+            .process(&Transaction::Deposit(DepositTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                amount: Decimal::new(10, 0),
+                timestamp: None,
+            }))
+            .unwrap();
+        let batch = vec![
+            Transaction::Deposit(DepositTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                amount: Decimal::new(20, 0),
+                timestamp: None,
+            }),
+            Transaction::Withdrawal(WithdrawalTransaction {
+                client: ClientId(1),
+                tx: TxId(2),
+                amount: Decimal::new(500, 0),
+                timestamp: None,
+            }),
+        ];
+
+        // when ...
+        let result = ledger.process_batch(&batch);
+
+        // then ...
+        assert_eq!(result.map_err(|error| error.index), Err(1));
+        let stored = ledger.transactions.get(TxId(1)).unwrap();
+        assert_eq!(stored.amount(), Decimal::new(10, 0));
+        assert_eq!(ledger.verify(), Ok(()));
+        ledger
+            .process(&Transaction::Dispute(DisputeTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                timestamp: None,
+            }))
+            .unwrap();
+        let account = ledger.accounts().get(ClientId(1)).unwrap();
+        assert_eq!(account.held(), Decimal::new(10, 0));
+    }
+
+    fn at(day: u32) -> Option<DateTime<Utc>> {
+        format!("2024-01-{day:02}T00:00:00Z").parse().ok()
+    }
+
+    fn deposit(tx: u64, day: u32) -> Transaction {
+        Transaction::Deposit(DepositTransaction {
+            client: ClientId(1),
+            tx: TxId(tx),
+            amount: Decimal::new(100, 0),
+            timestamp: at(day),
+        })
+    }
+
+    fn dispute(tx: u64, day: u32) -> Transaction {
+        Transaction::Dispute(DisputeTransaction {
+            client: ClientId(1),
+            tx: TxId(tx),
+            timestamp: at(day),
+        })
+    }
+
+    #[test]
+    fn test_dispute_after_window_is_rejected() {
+        // given ...
+        let policy = DisputePolicy::default().with_window(TimeDelta::days(10));
+        let mut ledger = Ledger::default().with_dispute_policy(policy);
+        ledger.process(&deposit(1, 1)).unwrap();
+        ledger.process(&deposit(2, 5)).unwrap();
+
+        // when ...
+        let late = ledger.process(&dispute(1, 12));
+        let timely = ledger.process(&dispute(2, 12));
+
+        // then ...
+        assert_eq!(
+            late,
+            Err(Error::DisputeWindowExpired {
+                client: ClientId(1),
+                tx: TxId(1)
+            })
+        );
+        assert_eq!(timely, Ok(()));
+        let account = ledger.accounts().get(ClientId(1)).unwrap();
+        assert_eq!(account.held(), Decimal::new(100, 0));
+    }
+
+    #[test]
+    fn test_open_dispute_is_resolved_automatically() {
+        // given ...
+        let policy = DisputePolicy::default().with_auto_resolve_after(TimeDelta::days(7));
+        let mut ledger = Ledger::default().with_dispute_policy(policy);
+        ledger.process(&deposit(1, 1)).unwrap();
+        ledger.process(&dispute(1, 2)).unwrap();
+
+        // when ...
+        ledger.process(&deposit(2, 8)).unwrap();
+        let before = ledger.accounts().get(ClientId(1)).unwrap().held();
+        ledger.process(&deposit(3, 9)).unwrap();
+        let chargeback = ledger.process(&Transaction::Chargeback(ChargebackTransaction {
+            client: ClientId(1),
+            tx: TxId(1),
+            timestamp: at(9),
+        }));
+
+        // then ...
+        assert_eq!(before, Decimal::new(100, 0));
+        let account = ledger.accounts().get(ClientId(1)).unwrap();
+        assert_eq!(account.held(), Decimal::ZERO);
+        assert_eq!(account.available(), Decimal::new(300, 0));
+        assert_eq!(
+            chargeback,
+            Err(Error::AccountStateError(
+                account_state::Error::DisputeNotFound {
+                    client: ClientId(1),
+                    tx: TxId(1)
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn test_rejected_row_resolves_no_disputes() {
+        // given ...
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        let policy = DisputePolicy::default().with_auto_resolve_after(TimeDelta::days(7));
+        let mut ledger = Ledger::default()
+            .with_dispute_policy(policy)
+            .with_observer(move |event: &Event| recorded.lock().unwrap().push(event.clone()));
+        ledger.process(&deposit(1, 1)).unwrap();
+        ledger.process(&dispute(1, 2)).unwrap();
+        events.lock().unwrap().clear();
+
+        // when ...
+        let rejected = ledger.process(&dispute(9, 10));
+        let held = ledger.accounts().get(ClientId(1)).unwrap().held();
+        ledger.process(&deposit(2, 10)).unwrap();
+
+        // then ...
+        assert!(rejected.is_err());
+        assert_eq!(held, Decimal::new(100, 0));
+        assert_eq!(
+            ledger.accounts().get(ClientId(1)).unwrap().held(),
+            Decimal::ZERO
+        );
+        let resolved = events.lock().unwrap().iter().position(|event| {
+            matches!(
+                event,
+                Event::DisputeResolved {
+                    automatic: true,
+                    ..
+                }
+            )
+        });
+        assert_eq!(resolved, Some(2));
+    }
+
+    #[test]
+    fn test_batch_resolves_disputes_automatically_once_applied() {
+        // given ...
+        let policy = DisputePolicy::default().with_auto_resolve_after(TimeDelta::days(7));
+        let mut ledger = Ledger::default().with_dispute_policy(policy);
+        ledger.process(&deposit(1, 1)).unwrap();
+        ledger.process(&dispute(1, 2)).unwrap();
+        let batch = |tx, amount| {
+            vec![
+                Transaction::Deposit(DepositTransaction {
+                    client: ClientId(2),
+                    tx: TxId(tx),
+                    amount: Decimal::new(10, 0),
+                    timestamp: at(10),
+                }),
+                Transaction::Withdrawal(WithdrawalTransaction {
+                    client: ClientId(2),
+                    tx: TxId(tx + 1),
+                    amount: Decimal::new(amount, 0),
+                    timestamp: at(10),
+                }),
+            ]
+        };
+
+        // when ...
+        let rejected = ledger.process_batch(&batch(2, 50));
+        let held = ledger.accounts().get(ClientId(1)).unwrap().held();
+        let applied = ledger.process_batch(&batch(4, 5));
+
+        // then ...
+        assert!(rejected.is_err());
+        assert_eq!(held, Decimal::new(100, 0));
+        assert_eq!(applied, Ok(()));
+        let account = ledger.accounts().get(ClientId(1)).unwrap();
+        assert_eq!(account.held(), Decimal::ZERO);
+        assert_eq!(account.available(), Decimal::new(100, 0));
+    }
+
+    #[test]
+    fn test_rejected_batch_restores_dispute_timers() {
+        // given ...
+        let policy = DisputePolicy::default().with_auto_resolve_after(TimeDelta::days(7));
+        let mut ledger = Ledger::default()
+            .with_dispute_policy(policy)
+            .with_history(true);
+        ledger.process(&deposit(1, 1)).unwrap();
+        ledger.process(&dispute(1, 2)).unwrap();
+        ledger
+            .process(&Transaction::Deposit(DepositTransaction {
+                client: ClientId(2),
+                tx: TxId(2),
+                amount: Decimal::new(50, 0),
+                timestamp: at(1),
+            }))
+            .unwrap();
+        // Day 10 is past client 1's automatic resolution, but the batch never stands.
+        let batch = vec![
+            Transaction::Dispute(DisputeTransaction {
+                client: ClientId(2),
+                tx: TxId(2),
+                timestamp: at(10),
+            }),
+            Transaction::Resolve(ResolveTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                timestamp: at(10),
+            }),
+            Transaction::Withdrawal(WithdrawalTransaction {
+                client: ClientId(2),
+                tx: TxId(3),
+                amount: Decimal::new(500, 0),
+                timestamp: at(10),
+            }),
+        ];
+
+        // when ...
+        let rejected = ledger.process_batch(&batch);
+        let held = ledger.accounts().get(ClientId(1)).unwrap().held();
+        ledger.process(&deposit(4, 20)).unwrap();
+
+        // then ...
+        assert_eq!(rejected.map_err(|e| e.index), Err(2));
+        assert_eq!(held, Decimal::new(100, 0));
+        assert_eq!(ledger.dispute_opened.len(), 0);
+        assert!(ledger.dispute_queue.is_empty());
+        let first = ledger.accounts().get(ClientId(1)).unwrap();
+        assert_eq!(first.held(), Decimal::ZERO);
+        assert_eq!(first.available(), Decimal::new(200, 0));
+        let second = ledger.accounts().get(ClientId(2)).unwrap();
+        assert_eq!(second.held(), Decimal::ZERO);
+        assert_eq!(second.available(), Decimal::new(50, 0));
+        let history = ledger.history().unwrap();
+        assert_eq!(history.len(ClientId(1)), 4);
+        assert_eq!(history.len(ClientId(2)), 1);
+        assert_eq!(ledger.verify(), Ok(()));
+    }
+
+    #[test]
+    fn test_account_at_point_in_time() {
+        // given ...
+        let mut ledger = Ledger::default().with_history(true);
+        ledger.process(&deposit(1, 1)).unwrap();
+        ledger.process(&deposit(2, 3)).unwrap();
+        ledger.process(&dispute(1, 5)).unwrap();
+        let batch = [deposit(3, 6), dispute(9, 6)];
+        assert!(ledger.process_batch(&batch).is_err());
+
+        // when ...
+        let after_first = ledger.account_at(ClientId(1), PointInTime::Transaction(TxId(1)));
+        let on_day_4 = ledger.account_at(ClientId(1), PointInTime::Timestamp(at(4).unwrap()));
+        let now = ledger.account_at(ClientId(1), PointInTime::Timestamp(at(31).unwrap()));
+        let rolled_back = ledger.account_at(ClientId(1), PointInTime::Transaction(TxId(3)));
+
+        // then ...
+        let after_first = after_first.unwrap();
+        assert_eq!(after_first.total(), Decimal::new(100, 0));
+        let on_day_4 = on_day_4.unwrap();
+        assert_eq!(on_day_4.available(), Decimal::new(200, 0));
+        assert_eq!(on_day_4.held(), Decimal::ZERO);
+        assert_eq!(now.as_ref(), ledger.accounts().get(ClientId(1)));
+        assert_eq!(rolled_back, None);
+    }
+
+    #[test]
+    fn test_observers_are_notified_of_events() {
+        // given ...
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        let mut ledger = Ledger::default()
+            .with_observer(move |event: &Event| recorded.lock().unwrap().push(event.clone()));
+        ledger.process(&deposit(1, 1)).unwrap();
+        let batch = [deposit(2, 2), dispute(9, 2)];
+
+        // when ...
+        ledger.process(&dispute(1, 2)).unwrap();
+        assert!(ledger.process_batch(&batch).is_err());
+        ledger
+            .process(&Transaction::Chargeback(ChargebackTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                timestamp: at(3),
+            }))
+            .unwrap();
+
+        // then ...
+        let amount = Decimal::new(100, 0);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                Event::FundsDeposited {
+                    client: ClientId(1),
+                    tx: TxId(1),
+                    amount
+                },
+                Event::FundsHeld {
+                    client: ClientId(1),
+                    tx: TxId(1),
+                    amount
+                },
+                Event::TransactionRejected {
+                    client: ClientId(1),
+                    tx: TxId(9),
+                    code: ErrorCode::DisputeTransactionNotFound,
+                    reason: Error::DisputeTransactionNotFound {
+                        client: ClientId(1),
+                        tx: TxId(9)
+                    },
+                },
+                Event::FundsChargedBack {
+                    client: ClientId(1),
+                    tx: TxId(1),
+                    amount
+                },
+                Event::AccountLocked {
+                    client: ClientId(1),
+                    reason: None
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ledger_with_observers_moves_to_another_thread() {
+        // given ...
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        let mut ledger = Ledger::default()
+            .with_observer(move |event: &Event| recorded.lock().unwrap().push(event.clone()));
+
+        // when ...
+        let processed = std::thread::spawn(move || ledger.process(&deposit(1, 1)))
+            .join()
+            .unwrap();
+
+        // then ...
+        assert_eq!(processed, Ok(()));
+        assert_eq!(events.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_freeze_without_a_transaction() {
+        // given ...
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        let mut ledger = Ledger::default()
+            .with_history(true)
+            .with_observer(move |event: &Event| recorded.lock().unwrap().push(event.clone()));
+        ledger.process(&deposit(1, 1)).unwrap();
+        events.lock().unwrap().clear();
+
+        // when ...
+        let frozen = ledger.freeze(ClientId(1));
+        let unknown = ledger.freeze(ClientId(2));
+
+        // then ...
+        assert_eq!(frozen, Ok(()));
+        let replayed = ledger.account_at(ClientId(1), PointInTime::Transaction(TxId(1)));
+        assert_eq!(
+            replayed.map(|account| account.status()),
+            Some(AccountStatus::Frozen)
+        );
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![Event::AccountFrozen {
+                client: ClientId(1)
+            }]
+        );
+        let unknown = unknown.unwrap_err();
+        assert_eq!(
+            unknown,
+            Error::AccountNotFound {
+                client: ClientId(2)
+            }
+        );
+        assert_eq!(unknown.code(), ErrorCode::AccountNotFound);
+    }
+
+    #[test]
+    fn test_rejected_batch_keeps_automatic_resolves() {
+        // given ...
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        let policy = DisputePolicy::default().with_auto_resolve_after(TimeDelta::days(7));
+        let mut ledger = Ledger::default()
+            .with_dispute_policy(policy)
+            .with_observer(move |event: &Event| recorded.lock().unwrap().push(event.clone()));
+        ledger.process(&deposit(1, 1)).unwrap();
+        ledger.process(&dispute(1, 2)).unwrap();
+        events.lock().unwrap().clear();
+
+        // when ...
+        ledger.process_batch(&[deposit(2, 10)]).unwrap();
+        assert!(
+            ledger
+                .process_batch(&[deposit(3, 11), dispute(9, 11)])
+                .is_err()
+        );
+
+        // then ...
+        let amount = Decimal::new(100, 0);
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![
+                Event::FundsDeposited {
+                    client: ClientId(1),
+                    tx: TxId(2),
+                    amount
+                },
+                Event::DisputeResolved {
+                    client: ClientId(1),
+                    tx: TxId(1),
+                    amount,
+                    automatic: true
+                },
+                Event::TransactionRejected {
+                    client: ClientId(1),
+                    tx: TxId(9),
+                    code: ErrorCode::DisputeTransactionNotFound,
+                    reason: Error::DisputeTransactionNotFound {
+                        client: ClientId(1),
+                        tx: TxId(9)
+                    },
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_risk_rules_reject_or_flag() {
+        // given ...
+        let rules = RiskRules::default()
+            .with_rule(
+                Rule::MaxAmount {
+                    r#type: TransactionType::Deposit,
+                    max: Decimal::new(150, 0),
+                },
+                Action::Reject,
+            )
+            .with_rule(
+                Rule::MaxCount {
+                    r#type: TransactionType::Deposit,
+                    max: 1,
+                    window: Window::Duration(TimeDelta::days(1)),
+                },
+                Action::Flag,
+            );
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        let mut ledger =
+            Ledger::default()
+                .with_risk_rules(rules)
+                .with_observer(move |event: &Event| {
+                    if let Event::TransactionFlagged { .. } = event {
+                        recorded.lock().unwrap().push(event.clone())
+                    }
+                });
+        ledger.process(&deposit(1, 1)).unwrap();
+
+        // when ...
+        let flagged = ledger.process(&deposit(2, 1));
+        let large = ledger.process(&Transaction::Deposit(DepositTransaction {
+            client: ClientId(1),
+            tx: TxId(3),
+            amount: Decimal::new(200, 0),
+            timestamp: at(3),
+        }));
+
+        // then ...
+        assert_eq!(flagged, Ok(()));
+        assert_eq!(
+            large,
+            Err(Error::RuleViolation {
+                client: ClientId(1),
+                tx: TxId(3),
+                rule: "deposit amount above 150".to_string()
+            })
+        );
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![Event::TransactionFlagged {
+                client: ClientId(1),
+                tx: TxId(2),
+                rule: "deposit count above 1 in 86400s".to_string()
+            }]
+        );
+        let account = ledger.accounts().get(ClientId(1)).unwrap();
+        assert_eq!(account.available(), Decimal::new(200, 0));
+    }
+
+    #[test]
+    fn test_lock_policy_locks_and_records_reason() {
+        // given ...
+        let policy = LockPolicy::default().with_max_open_disputes(1);
+        let mut ledger = Ledger::default()
+            .with_lock_policy(policy)
+            .with_history(true);
+        for tx in 1..=3 {
+            ledger.process(&deposit(tx, 1)).unwrap();
+        }
+        ledger.process(&dispute(1, 2)).unwrap();
+        let mut output = Vec::new();
+
+        // when ...
+        ledger.process(&dispute(2, 2)).unwrap();
+        let third = ledger.process(&dispute(3, 2));
+        let resolved = ledger.process(&Transaction::Resolve(ResolveTransaction {
+            client: ClientId(1),
+            tx: TxId(1),
+            timestamp: at(3),
+        }));
+        let charged_back = ledger.process(&Transaction::Chargeback(ChargebackTransaction {
+            client: ClientId(1),
+            tx: TxId(2),
+            timestamp: at(3),
+        }));
+        ledger.write_accounts(&mut output).unwrap();
+
+        // then ...
+        assert_eq!(
+            third,
+            Err(Error::AccountStateError(
+                account_state::Error::AccountLocked {
+                    client: ClientId(1)
+                }
+            ))
+        );
+        assert_eq!(resolved, Ok(()));
+        assert_eq!(charged_back, Ok(()));
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked,status,lock_reason\n\
+            1,200,0,200,true,locked,\"2 open disputes, above 1\"\n"
+        );
+        assert_eq!(ledger.verify(), Ok(()));
+        let statement = ledger.history().unwrap().statement(ClientId(1));
+        let locked: Vec<_> = statement.iter().map(|line| line.locked).collect();
+        assert_eq!(locked, [false, false, false, false, true, true, true]);
+    }
+
+    #[test]
+    fn test_lock_policy_counts_the_chargeback_that_breaks_the_ratio() {
+        // given ...
+        let policy = LockPolicy::default().with_max_chargeback_ratio(Decimal::new(25, 2));
+        let mut ledger = Ledger::default().with_lock_policy(policy);
+        for tx in 1..=3 {
+            ledger.process(&deposit(tx, 1)).unwrap();
+        }
+        ledger.process(&dispute(1, 2)).unwrap();
+
+        // when ...
+        ledger
+            .process(&Transaction::Chargeback(ChargebackTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                timestamp: at(3),
+            }))
+            .unwrap();
+
+        // then ...
+        let account = ledger.accounts().get(ClientId(1)).unwrap();
+        assert!(account.locked());
+        assert_eq!(
+            account.lock_reason().map(|reason| reason.to_string()),
+            Some("chargeback ratio 0.3333, above 0.25".to_string())
+        );
+    }
+
+    #[test]
+    fn test_chargeback_on_a_policy_locked_account_emits_no_second_lock() {
+        // given ...
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        let mut ledger = Ledger::default()
+            .with_lock_policy(LockPolicy::default().with_max_open_disputes(1))
+            .with_observer(move |event: &Event| recorded.lock().unwrap().push(event.clone()));
+        for tx in 1..=2 {
+            ledger.process(&deposit(tx, 1)).unwrap();
+            ledger.process(&dispute(tx, 1)).unwrap();
+        }
+
+        // when ...
+        ledger
+            .process(&Transaction::Chargeback(ChargebackTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                timestamp: None,
+            }))
+            .unwrap();
+
+        // then ...
+        let locks: Vec<_> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|event| match event {
+                Event::AccountLocked { reason, .. } => Some(reason.clone()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            locks,
+            vec![Some(LockReason::OpenDisputes { open: 2, max: 1 })]
+        );
+    }
+
+    #[test]
+    fn test_blocklisted_client_is_rejected_without_an_account() {
+        // given ...
+        let mut ledger = Ledger::default().with_blocklist(Blocklist::from_clients([ClientId(1)]));
+
+        // when ...
+        let result = ledger.process(&deposit(1, 1));
+
+        // then ...
+        assert_eq!(
+            result.as_ref().map_err(ToString::to_string),
+            Err("Account (1) is blocklisted, transaction 1 rejected".to_string())
+        );
+        assert_eq!(result.unwrap_err().code(), ErrorCode::ClientBlocked);
+        assert!(ledger.accounts().get(ClientId(1)).is_none());
+    }
+
+    #[test]
+    fn test_accounts_are_only_created_by_successful_transactions() {
+        // given ...
+        let mut ledger = Ledger::default();
+        let mut strict = Ledger::default().with_require_open(true);
+        let open = |tx| {
+            Transaction::Open(OpenTransaction {
+                client: ClientId(1),
+                tx: TxId(tx),
+                timestamp: None,
+            })
+        };
+        let withdrawal = Transaction::Withdrawal(WithdrawalTransaction {
+            client: ClientId(2),
+            tx: TxId(2),
+            amount: Decimal::new(100, 0),
+            timestamp: None,
+        });
+
+        // when ...
+        let dispute = ledger.process(&dispute(1, 1));
+        let withdrawal = ledger.process(&withdrawal);
+        let unopened = strict.process(&deposit(1, 1));
+        let opened = strict.process(&open(9));
+        let reopened = strict.process(&open(10));
+        let deposited = strict.process(&deposit(1, 1));
+
+        // then ...
+        assert!(dispute.is_err());
+        assert!(withdrawal.is_err());
+        assert_eq!(ledger.accounts().len(), 0);
+        assert_eq!(
+            unopened.as_ref().map_err(ToString::to_string),
+            Err("Account (1) is not open, transaction 1 rejected".to_string())
+        );
+        assert_eq!(unopened.unwrap_err().code(), ErrorCode::AccountNotOpen);
+        assert_eq!(opened, Ok(()));
+        assert_eq!(
+            reopened,
+            Err(Error::AccountAlreadyOpen {
+                client: ClientId(1),
+                tx: TxId(10)
+            })
+        );
+        assert_eq!(deposited, Ok(()));
+        assert_eq!(
+            strict.accounts().get(ClientId(1)).map(AccountState::total),
+            Some(Decimal::new(100, 0))
+        );
+    }
+
+    #[test]
+    fn test_account_lifecycle_from_kyc_to_payout() {
+        // given ...
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&events);
+        let mut ledger = Ledger::default()
+            .with_kyc_deposit_cap(Decimal::new(150, 0))
+            .with_observer(move |event: &Event| seen.lock().unwrap().push(event.clone()));
+        let open = |tx| {
+            Transaction::Open(OpenTransaction {
+                client: ClientId(1),
+                tx: TxId(tx),
+                timestamp: None,
+            })
+        };
+        let close = |tx| {
+            Transaction::Close(CloseTransaction {
+                client: ClientId(1),
+                tx: TxId(tx),
+                timestamp: None,
+            })
+        };
+
+        // when ...
+        ledger.process(&open(10)).unwrap();
+        ledger.process(&deposit(1, 1)).unwrap();
+        let capped = ledger.process(&deposit(2, 1));
+        ledger.process(&open(11)).unwrap();
+        let reopened = ledger.process(&open(12));
+        ledger.process(&deposit(2, 1)).unwrap();
+        ledger.freeze(ClientId(1)).unwrap();
+        let frozen = ledger.process(&close(13));
+        ledger.process(&open(14)).unwrap();
+        ledger.process(&close(15)).unwrap();
+
+        // then ...
+        assert_eq!(
+            capped.map_err(|e| e.to_string()),
+            Err("Account (1) transaction 2 would take deposits pending KYC above 150".to_string())
+        );
+        assert_eq!(
+            reopened.map_err(|e| e.code()),
+            Err(ErrorCode::AccountAlreadyOpen)
+        );
+        assert_eq!(frozen.map_err(|e| e.code()), Err(ErrorCode::AccountFrozen));
+        assert_eq!(
+            ledger.payouts(),
+            [Payout {
+                client: ClientId(1),
+                tx: TxId(15),
+                amount: Decimal::new(200, 0)
+            }]
+        );
+        assert_eq!(ledger.verify(), Ok(()));
+        let statuses: Vec<_> = events
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|event| match event {
+                Event::AccountOpened { status, .. } => Some(status.name()),
+                Event::AccountFrozen { .. } => Some("frozen"),
+                Event::AccountClosed { .. } => Some("closed"),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            statuses,
+            ["pending-kyc", "active", "frozen", "active", "closed"]
+        );
+        let mut output = Vec::new();
+        ledger.write_accounts(&mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,available,held,total,locked,status,lock_reason\n\
+            1,0,0,0,false,closed,\n"
+        );
+    }
+
+    #[test]
+    fn test_account_transactions_take_unique_ids() {
+        // given ...
+        let mut ledger = Ledger::default();
+        ledger.process(&deposit(1, 1)).unwrap();
+        ledger
+            .process(&Transaction::Open(OpenTransaction {
+                client: ClientId(2),
+                tx: TxId(2),
+                timestamp: None,
+            }))
+            .unwrap();
+
+        // when ...
+        let open = ledger.process(&Transaction::Open(OpenTransaction {
+            client: ClientId(3),
+            tx: TxId(1),
+            timestamp: None,
+        }));
+        let close = ledger.process(&Transaction::Close(CloseTransaction {
+            client: ClientId(2),
+            tx: TxId(2),
+            timestamp: None,
+        }));
+
+        // then ...
+        for result in [open, close] {
+            assert_eq!(
+                result.map_err(|e| e.code()),
+                Err(ErrorCode::DuplicateTransaction)
+            );
+        }
+        assert!(ledger.accounts().get(ClientId(3)).is_none());
+    }
+
+    #[test]
+    fn test_implicit_account_starts_pending_kyc_under_a_cap() {
+        // given ...
+        let mut ledger = Ledger::default().with_kyc_deposit_cap(Decimal::new(150, 0));
+
+        // when ...
+        let first = ledger.process(&deposit(1, 1));
+        let second = ledger.process(&deposit(2, 1));
+
+        // then ...
+        assert_eq!(first, Ok(()));
+        assert_eq!(second.map_err(|e| e.code()), Err(ErrorCode::KycDepositCap));
+        assert_eq!(
+            ledger.accounts().get(ClientId(1)).map(AccountState::status),
+            Some(AccountStatus::PendingKyc)
+        );
+    }
 }
@@ -1,12 +1,232 @@
+use crate::dialect::Dialect;
+use crate::error::{Coded, ErrorCode};
+use crate::id::IdMap;
+use crate::id::{ClientId, TxId};
+use crate::ledger;
 use crate::ledger::Ledger;
-use crate::transaction_reader::TransactionReader;
+use crate::metrics::{Metrics, MetricsFormat};
+use crate::transaction::{CsvTransaction, Transaction, rfc3339};
+use crate::transaction_reader;
+use crate::transaction_reader::{Row, TransactionReader};
+use crate::verification::VerificationReport;
+use chrono::{DateTime, Utc};
 use log::error;
 use std::io;
+use std::sync::Arc;
+use std::time::Instant;
+use thiserror::Error;
+use tracing::{debug_span, info_span};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("Row {}{} is malformed: {source}", location.row, location.suffix())]
+    MalformedRow {
+        location: Location,
+        source: transaction_reader::Error,
+    },
+    #[error("Row {}{} was rejected: {source}", location.row, location.suffix())]
+    RejectedTransaction {
+        location: Location,
+        source: ledger::Error,
+    },
+    #[error(
+        "Batch {batch} was rejected at row {}{}: {source}",
+        location.row,
+        location.suffix()
+    )]
+    RejectedBatch {
+        batch: String,
+        location: Location,
+        source: ledger::Error,
+    },
+    #[error(
+        "Ledger verification failed after row {}{}: {report}",
+        location.row,
+        location.suffix()
+    )]
+    Verification {
+        location: Location,
+        report: VerificationReport,
+    },
+    #[error("Failed to read {}: {error}", input.as_deref().unwrap_or("input"))]
+    Input {
+        input: Option<Arc<str>>,
+        error: io::Error,
+    },
+    #[error("Failed to write output: {0}")]
+    Output(#[from] anyhow::Error),
+}
+
+impl Error {
+    /// Where the offending row came from; `None` for input and output failures.
+    pub fn location(&self) -> Option<&Location> {
+        match self {
+            Error::MalformedRow { location, .. }
+            | Error::RejectedTransaction { location, .. }
+            | Error::RejectedBatch { location, .. }
+            | Error::Verification { location, .. } => Some(location),
+            Error::Input { .. } | Error::Output(_) => None,
+        }
+    }
+
+    /// Whether the run cannot go on regardless of strict mode: an input could not be
+    /// read, an output could not be written or the ledger failed verification.
+    pub fn is_fatal(&self) -> bool {
+        matches!(
+            self,
+            Error::Verification { .. } | Error::Input { .. } | Error::Output(_)
+        )
+    }
+
+    pub fn batch(&self) -> Option<&str> {
+        match self {
+            Error::RejectedBatch { batch, .. } => Some(batch),
+            _ => None,
+        }
+    }
+
+    /// The client of the offending row, when the row was readable enough to tell.
+    pub fn client(&self) -> Option<ClientId> {
+        match self {
+            Error::MalformedRow {
+                source: transaction_reader::Error::Invalid { source, .. },
+                ..
+            } => Some(source.client()),
+            Error::RejectedTransaction { source, .. } | Error::RejectedBatch { source, .. } => {
+                Some(source.client())
+            }
+            _ => None,
+        }
+    }
+
+    /// The transaction of the offending row, when the row was readable enough to tell.
+    pub fn tx(&self) -> Option<TxId> {
+        match self {
+            Error::MalformedRow {
+                source: transaction_reader::Error::Invalid { source, .. },
+                ..
+            } => Some(source.tx()),
+            Error::RejectedTransaction { source, .. } | Error::RejectedBatch { source, .. } => {
+                source.tx()
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Coded for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::MalformedRow { source, .. } => source.code(),
+            Error::RejectedTransaction { source, .. } | Error::RejectedBatch { source, .. } => {
+                source.code()
+            }
+            Error::Verification { .. } => ErrorCode::InvariantViolation,
+            Error::Input { .. } | Error::Output(_) => ErrorCode::Io,
+        }
+    }
+}
+
+/// Called with every row a non-strict run skips.
+type ErrorHandler = Box<dyn FnMut(&Error) + Send>;
+
+/// Called with every readable transaction once its row or batch is settled, and the
+/// rejection when it was not applied.
+type TransactionHandler = Box<dyn FnMut(&Transaction, Option<&Error>) + Send>;
+
+#[derive(Default)]
+struct Handlers {
+    on_error: Option<ErrorHandler>,
+    on_transaction: Option<TransactionHandler>,
+}
+
+impl Handlers {
+    fn report(&mut self, error: &Error) {
+        if let Some(handler) = self.on_error.as_mut() {
+            handler(error);
+        }
+    }
+
+    fn settled(&mut self, transactions: &[Transaction], error: Option<&Error>) {
+        if let Some(handler) = self.on_transaction.as_mut() {
+            for transaction in transactions {
+                handler(transaction, error);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    #[default]
+    Csv,
+    Json,
+}
+
+/// Where a row came from. `row` counts data rows within its input, while `line` is the
+/// physical line, which differs once quoted fields span lines. `timestamp` is the row's
+/// own timestamp, when it has a readable one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub input: Option<Arc<str>>,
+    pub line: u64,
+    pub row: u64,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+impl Location {
+    fn parts(&self) -> Vec<String> {
+        let input = self
+            .input
+            .as_ref()
+            .map(|input| format!("{input}:{}", self.line));
+        input
+            .into_iter()
+            .chain(self.timestamp.as_ref().map(rfc3339))
+            .collect()
+    }
+
+    fn suffix(&self) -> String {
+        match self.parts() {
+            parts if parts.is_empty() => String::new(),
+            parts => format!(" ({})", parts.join(", ")),
+        }
+    }
+
+    fn prefix(&self) -> String {
+        self.parts()
+            .into_iter()
+            .map(|part| format!("{part}: "))
+            .collect()
+    }
+}
+
+/// Counts of everything a run refused to apply.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RunSummary {
+    pub rejected_rows: u64,
+    pub rejected_transactions: u64,
+    pub rejected_batches: u64,
+}
+
+impl RunSummary {
+    pub fn is_clean(&self) -> bool {
+        self.rejected_rows == 0 && self.rejected_transactions == 0
+    }
+}
 
 pub struct LedgerSystem<R, W> {
     ledger: Ledger,
-    reader: R,
+    inputs: Vec<(Option<String>, R)>,
     writer: W,
+    format: OutputFormat,
+    journal: Option<csv::Writer<Box<dyn io::Write + Send>>>,
+    strict: bool,
+    dialect: Dialect,
+    ids: Option<(IdMap, Box<dyn io::Write + Send>)>,
+    handlers: Handlers,
+    metrics: Metrics,
+    metrics_sink: Option<(Box<dyn io::Write + Send>, MetricsFormat)>,
 }
 
 impl<R, W> LedgerSystem<R, W>
@@ -17,24 +237,362 @@ where
     pub fn new(ledger: Ledger, reader: R, writer: W) -> Self {
         LedgerSystem {
             ledger,
-            reader,
+            inputs: vec![(None, reader)],
+            writer,
+            format: OutputFormat::default(),
+            journal: None,
+            strict: false,
+            dialect: Dialect::default(),
+            ids: None,
+            handlers: Handlers::default(),
+            metrics: Metrics::default(),
+            metrics_sink: None,
+        }
+    }
+
+    /// Processes several named inputs in order against the same ledger, so later inputs
+    /// can dispute deposits from earlier ones. Rejections are reported with the input
+    /// name and line.
+    pub fn from_inputs(ledger: Ledger, inputs: Vec<(String, R)>, writer: W) -> Self {
+        LedgerSystem {
+            ledger,
+            inputs: inputs
+                .into_iter()
+                .map(|(name, reader)| (Some(name), reader))
+                .collect(),
             writer,
+            format: OutputFormat::default(),
+            journal: None,
+            strict: false,
+            dialect: Dialect::default(),
+            ids: None,
+            handlers: Handlers::default(),
+            metrics: Metrics::default(),
+            metrics_sink: None,
         }
     }
 
-    pub fn run(mut self) {
-        let mut transactions = TransactionReader::new(self.reader);
+    pub fn with_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Records every applied transaction, in the input CSV format, so the final state can
+    /// later be rebuilt by replaying the journal.
+    pub fn with_journal<J>(mut self, journal: J) -> Self
+    where
+        J: io::Write + Send + 'static,
+    {
+        let journal: Box<dyn io::Write + Send> = Box::new(journal);
+        self.journal = Some(csv::Writer::from_writer(journal));
+        self
+    }
+
+    /// Stops the run at the first malformed row or rejected transaction instead of logging
+    /// it and moving on. No account states, metrics or id table are written when a strict
+    /// run fails, but the journal may already hold the transactions applied before it, so
+    /// callers writing files should only keep them once the run succeeds.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Reads every input with a partner's delimiter, column names and type values.
+    pub fn with_dialect(mut self, dialect: Dialect) -> Self {
+        self.dialect = dialect;
+        self
+    }
+
+    /// Hands every malformed row and rejected transaction a non-strict run skips to
+    /// `handler`, as the [`Error`] a strict run would have stopped with, so callers can
+    /// count and route them by [`Coded::code`]. Every malformed member of a rejected
+    /// batch is reported on its own.
+    pub fn with_error_handler<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(&Error) + Send + 'static,
+    {
+        self.handlers.on_error = Some(Box::new(handler));
+        self
+    }
+
+    /// Hands every readable transaction to `handler` in input order once it was applied
+    /// or rejected, with the [`Error`] that rejected it. Every member of a rejected batch
+    /// gets the batch's error, or the first malformed member's.
+    pub fn with_transaction_handler<F>(mut self, handler: F) -> Self
+    where
+        F: FnMut(&Transaction, Option<&Error>) + Send + 'static,
+    {
+        self.handlers.on_transaction = Some(Box::new(handler));
+        self
+    }
+
+    /// Writes the run's [`Metrics`] to `sink` once every input is applied.
+    pub fn with_metrics<M>(mut self, sink: M, format: MetricsFormat) -> Self
+    where
+        M: io::Write + Send + 'static,
+    {
+        self.metrics_sink = Some((Box::new(sink), format));
+        self
+    }
+
+    /// Reads the `client` and `tx` columns as partner string ids, mapping them to internal
+    /// ids through `ids`. The ledger's blocklist then lists partner ids too. The updated
+    /// mapping is saved to `store` once every input is applied, so the same partner ids
+    /// map to the same accounts on the next run.
+    pub fn with_ids<S>(mut self, ids: IdMap, store: S) -> Self
+    where
+        S: io::Write + Send + 'static,
+    {
+        self.ledger.set_client_names(ids.client_names());
+        self.ids = Some((ids, Box::new(store)));
+        self
+    }
 
-        for transaction in transactions.iter() {
-            let _ = self
-                .ledger
-                .process(&transaction)
-                .inspect_err(|e| error!("{e}"));
+    /// Applies every readable transaction, logging rejections, then writes the final
+    /// account states. Outside of strict mode only failing to write output is an error.
+    ///
+    /// Consecutive rows sharing a `batch` value are applied all-or-nothing and rejected
+    /// as a unit when any member is malformed or rejected.
+    pub fn run(mut self) -> Result<RunSummary, Error> {
+        let summary = self.apply_inputs()?;
+        match self.format {
+            OutputFormat::Csv => self.ledger.write_accounts(self.writer)?,
+            OutputFormat::Json => self.ledger.write_accounts_json(self.writer)?,
         }
+        Ok(summary)
+    }
 
-        let _ = self
-            .ledger
-            .write_accounts(self.writer)
-            .inspect_err(|e| error!("{e}"));
+    /// Like [`LedgerSystem::run`], but hands back the ledger instead of writing the
+    /// account states, for callers that want to query it.
+    pub fn into_ledger(mut self) -> Result<(Ledger, RunSummary), Error> {
+        let summary = self.apply_inputs()?;
+        Ok((self.ledger, summary))
+    }
+
+    fn apply_inputs(&mut self) -> Result<RunSummary, Error> {
+        let started = Instant::now();
+        let accounts_before = self.ledger.accounts().len();
+        let mut summary = RunSummary::default();
+        let applied = std::mem::take(&mut self.inputs)
+            .into_iter()
+            .try_for_each(|(input, reader)| self.run_input(input, reader, &mut summary));
+        applied?;
+        self.metrics
+            .finish(&self.ledger, accounts_before, started.elapsed());
+        self.ledger
+            .flush_observers()
+            .map_err(|e| anyhow::anyhow!("Failed to write events: {e}"))?;
+        if let Some((sink, format)) = self.metrics_sink.take() {
+            self.metrics
+                .write(sink, format)
+                .map_err(anyhow::Error::from)?;
+        }
+
+        if let Some(mut journal) = self.journal.take() {
+            journal.flush().map_err(anyhow::Error::from)?;
+        }
+        if let Some((ids, store)) = self.ids.take() {
+            ids.save(store).map_err(anyhow::Error::from)?;
+        }
+        Ok(summary)
+    }
+
+    fn run_input(
+        &mut self,
+        input: Option<String>,
+        reader: R,
+        summary: &mut RunSummary,
+    ) -> Result<(), Error> {
+        let _input = info_span!("input", input = input.as_deref()).entered();
+        let mut transactions = TransactionReader::with_dialect(reader, self.dialect.clone());
+        let mut store = None;
+        if let Some((ids, writer)) = self.ids.take() {
+            transactions = transactions.with_ids(ids);
+            store = Some(writer);
+        }
+        let mut rows = transactions.rows().zip(1u64..).peekable();
+        let input: Option<Arc<str>> = input.map(Arc::from);
+        let location = |line, row, transaction: &Result<Transaction, _>| Location {
+            input: input.clone(),
+            line,
+            row,
+            timestamp: transaction.as_ref().ok().and_then(Transaction::timestamp),
+        };
+
+        while let Some((row, number)) = rows.next() {
+            // A row that could not be read ends the input; what follows it is lost.
+            let row = match row.transaction {
+                Err(transaction_reader::Error::Io(error)) => {
+                    return Err(Error::Input {
+                        input: input.clone(),
+                        error,
+                    });
+                }
+                transaction => Row { transaction, ..row },
+            };
+            let _row = debug_span!(
+                "row",
+                line = row.line,
+                row = number,
+                batch = row.batch.as_deref()
+            )
+            .entered();
+            let mut group = vec![(
+                location(row.line, number, &row.transaction),
+                row.transaction,
+            )];
+            if let Some(batch) = &row.batch {
+                while let Some((next, _)) = rows.peek()
+                    && next.batch.as_ref() == Some(batch)
+                {
+                    let (next, number) = rows.next().expect("peeked row exists");
+                    group.push((
+                        location(next.line, number, &next.transaction),
+                        next.transaction,
+                    ));
+                }
+            }
+            let first = group[0].0.clone();
+            self.metrics.rows_read += group.len() as u64;
+            let started = Instant::now();
+            let applied = apply(
+                &mut self.ledger,
+                row.batch,
+                group,
+                self.strict,
+                summary,
+                &mut self.handlers,
+                &mut self.metrics,
+            );
+            self.metrics.observe(started.elapsed());
+            let applied = applied?;
+            if let Some(report) = self.ledger.take_violations() {
+                return Err(Error::Verification {
+                    location: first,
+                    report,
+                });
+            }
+            for transaction in &applied {
+                self.metrics.applied(transaction.r#type());
+            }
+            if let Some(journal) = self.journal.as_mut() {
+                for transaction in &applied {
+                    journal
+                        .serialize(CsvTransaction::from(transaction))
+                        .map_err(anyhow::Error::from)?;
+                }
+            }
+        }
+        drop(rows);
+        if let Some(store) = store {
+            self.ids = transactions.into_ids().map(|ids| (ids, store));
+        }
+        Ok(())
+    }
+}
+
+/// Applies one unbatched row or one whole batch, returning the transactions that were
+/// applied.
+fn apply(
+    ledger: &mut Ledger,
+    batch: Option<String>,
+    group: Vec<(Location, Result<Transaction, transaction_reader::Error>)>,
+    strict: bool,
+    summary: &mut RunSummary,
+    handlers: &mut Handlers,
+    metrics: &mut Metrics,
+) -> Result<Vec<Transaction>, Error> {
+    let members = group.len() as u64;
+    let mut locations = Vec::with_capacity(group.len());
+    let mut transactions = Vec::with_capacity(group.len());
+    let mut malformed = Vec::new();
+    for (location, transaction) in group {
+        match transaction {
+            Ok(transaction) => {
+                locations.push(location);
+                transactions.push(transaction);
+            }
+            Err(source) if strict => {
+                metrics.rejected(source.code(), members);
+                return Err(Error::MalformedRow { location, source });
+            }
+            Err(e) => malformed.push((location, e)),
+        }
+    }
+    if let Some((location, e)) = malformed.first() {
+        match &batch {
+            Some(batch) => {
+                error!(
+                    "Batch {batch} was rejected at row {}{}: {e}",
+                    location.row,
+                    location.suffix()
+                );
+                summary.rejected_batches += 1;
+            }
+            None => error!("{}{e}", location.prefix()),
+        }
+        summary.rejected_rows += malformed.len() as u64;
+        summary.rejected_transactions += transactions.len() as u64;
+        metrics.rejected(e.code(), members);
+        let mut malformed = malformed
+            .into_iter()
+            .map(|(location, source)| Error::MalformedRow { location, source });
+        if let Some(first) = malformed.next() {
+            handlers.settled(&transactions, Some(&first));
+            handlers.report(&first);
+        }
+        for error in malformed {
+            handlers.report(&error);
+        }
+        return Ok(Vec::new());
+    }
+
+    let result = match batch {
+        // Unbatched rows go straight through so a rejection keeps its usual side effects.
+        None => transactions
+            .first()
+            .map_or(Ok(()), |transaction| ledger.process(transaction))
+            .map_err(|source| ledger::BatchError { index: 0, source }),
+        Some(_) => ledger.process_batch(&transactions),
+    };
+    let Err(e) = result else {
+        handlers.settled(&transactions, None);
+        return Ok(transactions);
+    };
+    let location = locations.swap_remove(e.index);
+    metrics.rejected(e.source.code(), members);
+    if !strict {
+        match &batch {
+            Some(batch) => {
+                error!(
+                    "Batch {batch} was rejected at row {}{}: {e}",
+                    location.row,
+                    location.suffix()
+                );
+                summary.rejected_transactions += members;
+                summary.rejected_batches += 1;
+            }
+            None => {
+                error!("{}{e}", location.prefix());
+                summary.rejected_transactions += 1;
+            }
+        }
+    }
+    let error = match batch {
+        Some(batch) => Error::RejectedBatch {
+            batch,
+            location,
+            source: e.source,
+        },
+        None => Error::RejectedTransaction {
+            location,
+            source: e.source,
+        },
+    };
+    handlers.settled(&transactions, Some(&error));
+    if strict {
+        return Err(error);
     }
+    handlers.report(&error);
+    Ok(Vec::new())
 }
@@ -1,9 +1,24 @@
 pub mod account_state;
 pub mod account_store;
+pub mod blocklist;
+pub mod compression;
+pub mod dialect;
+pub mod dispute_policy;
+pub mod error;
+pub mod event;
+pub mod history;
+pub mod id;
 pub mod ledger;
 pub mod ledger_system;
+pub mod lock_policy;
+pub mod metrics;
+pub mod payout;
+pub mod risk_rules;
+pub mod statement;
 pub mod stored_transaction;
 pub mod transaction;
+pub mod transaction_generator;
 pub mod transaction_reader;
 pub mod transaction_store;
 pub mod transaction_type;
+pub mod verification;
@@ -0,0 +1,193 @@
+use crate::account_state::AccountState;
+use crate::error::{Coded, ErrorCode};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize, Serializer};
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Parse(#[from] toml::de::Error),
+}
+
+impl Coded for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::Io(_) => ErrorCode::Io,
+            Error::Parse(_) => ErrorCode::InvalidConfig,
+        }
+    }
+}
+
+/// Which limit of a [`LockPolicy`] locked an account, with the value that broke it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LockReason {
+    OpenDisputes { open: usize, max: usize },
+    ChargebackRatio { ratio: Decimal, max: Decimal },
+    HeldRatio { ratio: Decimal, max: Decimal },
+}
+
+impl fmt::Display for LockReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LockReason::OpenDisputes { open, max } => {
+                write!(f, "{open} open disputes, above {max}")
+            }
+            LockReason::ChargebackRatio { ratio, max } => {
+                write!(f, "chargeback ratio {ratio}, above {max}")
+            }
+            LockReason::HeldRatio { ratio, max } => write!(f, "held ratio {ratio}, above {max}"),
+        }
+    }
+}
+
+impl Serialize for LockReason {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+/// Limits beyond which an account is locked, on top of the lock every chargeback causes.
+/// Evaluated against the account after each transaction applied to it, including the
+/// chargeback that just locked it. Disputes open when the lock is placed can still settle.
+///
+/// The TOML form, with every limit optional:
+///
+/// ```toml
+/// max_open_disputes = 3
+/// max_chargeback_ratio = "0.05"  # chargebacks per deposit, by count
+/// max_held_ratio = "0.5"         # held funds as a share of total funds
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LockPolicy {
+    max_open_disputes: Option<usize>,
+    max_chargeback_ratio: Option<Decimal>,
+    max_held_ratio: Option<Decimal>,
+}
+
+impl LockPolicy {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    fn parse(config: &str) -> Result<Self, Error> {
+        Ok(toml::from_str(config)?)
+    }
+
+    pub fn with_max_open_disputes(mut self, max: usize) -> Self {
+        self.max_open_disputes = Some(max);
+        self
+    }
+
+    pub fn with_max_chargeback_ratio(mut self, max: Decimal) -> Self {
+        self.max_chargeback_ratio = Some(max);
+        self
+    }
+
+    pub fn with_max_held_ratio(mut self, max: Decimal) -> Self {
+        self.max_held_ratio = Some(max);
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == LockPolicy::default()
+    }
+
+    /// The first limit the account breaks, if any.
+    pub fn evaluate(&self, account: &AccountState) -> Option<LockReason> {
+        if let Some(max) = self.max_open_disputes
+            && account.open_disputes() > max
+        {
+            return Some(LockReason::OpenDisputes {
+                open: account.open_disputes(),
+                max,
+            });
+        }
+        if let Some(max) = self.max_chargeback_ratio
+            && account.deposit_count() > 0
+        {
+            let ratio =
+                Decimal::from(account.chargeback_count()) / Decimal::from(account.deposit_count());
+            if ratio > max {
+                return Some(LockReason::ChargebackRatio {
+                    ratio: ratio.round_dp(4),
+                    max,
+                });
+            }
+        }
+        if let Some(max) = self.max_held_ratio
+            && account.total() > Decimal::ZERO
+            && let Some(ratio) = account.held().checked_div(account.total())
+            && ratio > max
+        {
+            return Some(LockReason::HeldRatio {
+                ratio: ratio.round_dp(4),
+                max,
+            });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::{ClientId, TxId};
+    use crate::transaction::DepositTransaction;
+
+    #[test]
+    fn test_evaluate_limits() {
+        // given ...
+        let policy: LockPolicy =
+            toml::from_str("max_open_disputes = 1\nmax_held_ratio = \"0.5\"\n").unwrap();
+        let mut account = AccountState::new(ClientId(1));
+        for tx in 1..=3 {
+            account.deposit(Decimal::new(10, 0)).unwrap();
+            let deposit = DepositTransaction {
+                client: ClientId(1),
+                tx: TxId(tx),
+                amount: Decimal::new(10, 0),
+                timestamp: None,
+            };
+            if tx < 3 {
+                account.dispute(&(&deposit).into()).unwrap();
+            }
+        }
+
+        // when ...
+        let both = policy.evaluate(&account);
+        let held = policy.clone().with_max_open_disputes(2).evaluate(&account);
+
+        // then ...
+        assert_eq!(both, Some(LockReason::OpenDisputes { open: 2, max: 1 }));
+        assert_eq!(
+            held.map(|reason| reason.to_string()),
+            Some("held ratio 0.6667, above 0.5".to_string())
+        );
+        let relaxed = policy
+            .with_max_open_disputes(2)
+            .with_max_held_ratio(Decimal::ONE);
+        assert_eq!(relaxed.evaluate(&account), None);
+    }
+
+    #[test]
+    fn test_invalid_policy() {
+        // given ...
+        let config = "max_open_disputes = \"two\"\n";
+
+        // when ...
+        let result = LockPolicy::parse(config);
+
+        // then ...
+        let error = result.unwrap_err();
+        assert!(matches!(error, Error::Parse(_)));
+        assert_eq!(error.code(), ErrorCode::InvalidConfig);
+    }
+}
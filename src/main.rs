@@ -1,12 +1,837 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
+use glowing_fiesta::blocklist::Blocklist;
+use glowing_fiesta::compression::Encoder;
+use glowing_fiesta::dialect::Dialect;
+use glowing_fiesta::dispute_policy::DisputePolicy;
+use glowing_fiesta::event::JsonLinesObserver;
+use glowing_fiesta::history::PointInTime;
+use glowing_fiesta::id::{ClientId, IdMap, TxId};
 use glowing_fiesta::ledger::Ledger;
-use glowing_fiesta::ledger_system::LedgerSystem;
+use glowing_fiesta::ledger_system;
+use glowing_fiesta::ledger_system::{LedgerSystem, OutputFormat, RunSummary};
+use glowing_fiesta::lock_policy::LockPolicy;
+use glowing_fiesta::metrics::MetricsFormat;
+use glowing_fiesta::payout::PayoutObserver;
+use glowing_fiesta::risk_rules::RiskRules;
+use glowing_fiesta::statement;
+use glowing_fiesta::statement::StatementLine;
+use glowing_fiesta::transaction::{CsvTransaction, Transaction};
+use glowing_fiesta::transaction_reader;
+use glowing_fiesta::transaction_reader::TransactionReader;
+use glowing_fiesta::transaction_type::TransactionType;
+use log::error;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::fs;
 use std::fs::File;
-use std::{env, io};
+use std::io;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError, mpsc};
+use std::time::Duration;
+use tracing_subscriber::EnvFilter;
+use tracing_subscriber::fmt::format::FmtSpan;
 
-fn main() {
-    env_logger::init();
-    let input_path = env::args().nth(1).expect("The input file path is required");
-    let input_file = File::open(&input_path).expect("Failed to open input file");
+/// Applies partner transaction files to client accounts.
+///
+/// Exit codes: 0 when every row was applied, 1 when some rows or transactions were
+/// rejected, 2 on a fatal error such as an unreadable input or unwritable output.
+#[derive(Debug, Parser)]
+#[command(name = "glowing-fiesta")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+    /// `json` logs one JSON object per line, with the tracing spans (input, row,
+    /// transaction) each message was logged in and how long every span took.
+    #[arg(long, global = true, value_enum, default_value_t = LogFormat::Text)]
+    log_format: LogFormat,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum LogFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Apply transaction CSVs in order and write the resulting account states.
+    Process {
+        /// Files, directories (read in lexicographic order), glob patterns or `-` for stdin.
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        ledger: LedgerArgs,
+        #[command(flatten)]
+        dialect: DialectArgs,
+        /// Write a journal of every applied transaction that `replay` can rebuild from.
+        #[arg(long)]
+        snapshot: Option<PathBuf>,
+        /// Treat client and tx ids as partner strings, mapped to internal ids through this
+        /// table. The table is created when missing and updated after the run.
+        #[arg(long)]
+        ids: Option<PathBuf>,
+        #[command(flatten)]
+        metrics: MetricsArgs,
+    },
+    /// Parse transaction CSVs and report malformed rows without applying anything.
+    Validate {
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        #[command(flatten)]
+        dialect: DialectArgs,
+        /// Also apply the rows to a scratch ledger, stopping at the first one after which
+        /// `Ledger::verify` finds a violation.
+        #[arg(long)]
+        verify_each: bool,
+    },
+    /// Rebuild account states from a journal written by `process --snapshot`.
+    Replay {
+        journal: String,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        ledger: LedgerArgs,
+    },
+    /// Print every transaction for one client with the outcome and resulting balances.
+    Inspect {
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        #[arg(long)]
+        client: ClientId,
+        #[command(flatten)]
+        ledger: LedgerArgs,
+        #[command(flatten)]
+        dialect: DialectArgs,
+    },
+    /// Print one client's account as it was right after a transaction or at a time.
+    Balance {
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        #[arg(long)]
+        client: ClientId,
+        #[command(flatten)]
+        at: At,
+        #[command(flatten)]
+        output: OutputArgs,
+        #[command(flatten)]
+        ledger: LedgerArgs,
+        #[command(flatten)]
+        dialect: DialectArgs,
+    },
+    /// Print every transaction that affected one or all clients with running balances.
+    Statement {
+        #[arg(required = true)]
+        inputs: Vec<String>,
+        #[command(flatten)]
+        statement: StatementArgs,
+        #[command(flatten)]
+        ledger: LedgerArgs,
+        #[command(flatten)]
+        dialect: DialectArgs,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+struct OutputArgs {
+    #[arg(long, value_enum, default_value_t = Format::Csv)]
+    format: Format,
+    /// Output file; defaults to stdout.
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+/// Options that change how transactions are applied.
+#[derive(Debug, clap::Args)]
+struct LedgerArgs {
+    /// Stop at the first malformed row or rejected transaction without writing output.
+    #[arg(long)]
+    strict: bool,
+    /// Seconds a timestamped transaction may trail the client's latest one before it is
+    /// rejected as out of order.
+    #[arg(long, default_value_t = 0)]
+    timestamp_tolerance: u32,
+    /// TOML file with dispute windows and the automatic resolution limit.
+    #[arg(long)]
+    dispute_policy: Option<PathBuf>,
+    /// TOML file with amount, count and volume limits on deposits and withdrawals.
+    #[arg(long)]
+    risk_rules: Option<PathBuf>,
+    /// TOML file with open dispute, chargeback ratio and held ratio limits that lock an
+    /// account.
+    #[arg(long)]
+    lock_policy: Option<PathBuf>,
+    /// File of client ids whose transactions are rejected, one per line. Reloaded when it
+    /// changes during the run.
+    #[arg(long)]
+    blocklist: Option<PathBuf>,
+    /// Only open accounts through `open` transactions; deposits and withdrawals for
+    /// clients without an account are rejected.
+    #[arg(long)]
+    require_open: bool,
+    /// New accounts start pending KYC and accept at most this much in deposits until an
+    /// `open` row approves them.
+    #[arg(long)]
+    kyc_deposit_cap: Option<Decimal>,
+    /// Write the payout of every closed account to this CSV file.
+    #[arg(long)]
+    payouts: Option<PathBuf>,
+    /// Write every ledger event to this file as JSON lines.
+    #[arg(long)]
+    events: Option<PathBuf>,
+    /// Verify the whole ledger after every transaction and stop at the first row that
+    /// breaks an invariant. Slow; meant for debugging.
+    #[arg(long)]
+    verify_each: bool,
+}
+
+#[derive(Debug, clap::Args)]
+struct StatementArgs {
+    /// Only this client; defaults to every client, in ascending order.
+    #[arg(long)]
+    client: Option<ClientId>,
+    #[arg(long, value_enum, default_value_t = StatementFormat::Text)]
+    format: StatementFormat,
+    /// Output file; defaults to stdout.
+    #[arg(long, short)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum StatementFormat {
+    Csv,
+    Text,
+}
+
+#[derive(Debug, clap::Args)]
+struct MetricsArgs {
+    /// Write the run's metrics to this file once every input is applied.
+    #[arg(long)]
+    metrics: Option<PathBuf>,
+    #[arg(long, value_enum, default_value_t = ExportFormat::Prometheus)]
+    metrics_format: ExportFormat,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ExportFormat {
+    Prometheus,
+    Json,
+}
+
+impl From<ExportFormat> for MetricsFormat {
+    fn from(format: ExportFormat) -> Self {
+        match format {
+            ExportFormat::Prometheus => MetricsFormat::Prometheus,
+            ExportFormat::Json => MetricsFormat::Json,
+        }
+    }
+}
+
+#[derive(Debug, clap::Args)]
+struct DialectArgs {
+    /// TOML file describing a partner's delimiter, column names and type values.
+    #[arg(long)]
+    dialect: Option<PathBuf>,
+}
+
+impl DialectArgs {
+    fn load(&self) -> anyhow::Result<Dialect> {
+        match &self.dialect {
+            Some(path) => Dialect::load(path)
+                .map_err(|e| anyhow::anyhow!("Failed to load dialect {}: {e}", path.display())),
+            None => Ok(Dialect::default()),
+        }
+    }
+}
+
+/// The point in time `balance` reports, given by exactly one of `--tx` or `--timestamp`.
+#[derive(Debug, clap::Args)]
+#[group(required = true, multiple = false)]
+struct At {
+    /// Right after this deposit or withdrawal was applied.
+    #[arg(long)]
+    tx: Option<TxId>,
+    /// After every transaction stamped at or before this RFC 3339 time.
+    #[arg(long)]
+    timestamp: Option<DateTime<Utc>>,
+}
+
+impl From<At> for PointInTime {
+    fn from(at: At) -> Self {
+        match at.tx {
+            Some(tx) => PointInTime::Transaction(tx),
+            None => PointInTime::Timestamp(at.timestamp.expect("the group requires one")),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Format {
+    Csv,
+    Json,
+}
+
+impl From<Format> for OutputFormat {
+    fn from(format: Format) -> Self {
+        match format {
+            Format::Csv => OutputFormat::Csv,
+            Format::Json => OutputFormat::Json,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct InspectRow {
+    r#type: TransactionType,
+    client: ClientId,
+    tx: TxId,
+    amount: Option<Decimal>,
+    timestamp: Option<DateTime<Utc>>,
+    result: String,
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
+}
+
+impl InspectRow {
+    fn automatic(line: StatementLine) -> Self {
+        InspectRow {
+            r#type: line.r#type,
+            client: line.client,
+            tx: line.tx,
+            amount: None,
+            timestamp: line.timestamp,
+            result: String::from("resolved automatically"),
+            available: line.available,
+            held: line.held,
+            total: line.total,
+            locked: line.locked,
+        }
+    }
+}
+
+const EXIT_REJECTIONS: u8 = 1;
+const EXIT_FATAL: u8 = 2;
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+    match cli.log_format {
+        LogFormat::Text => env_logger::init(),
+        // Both filter on RUST_LOG; log records are forwarded into the current span.
+        LogFormat::Json => tracing_subscriber::fmt()
+            .json()
+            .with_env_filter(EnvFilter::from_default_env())
+            .with_span_events(FmtSpan::CLOSE)
+            .with_writer(io::stderr)
+            .init(),
+    }
+
+    let result = match cli.command {
+        Command::Process {
+            inputs,
+            output,
+            ledger,
+            dialect,
+            snapshot,
+            ids,
+            metrics,
+        } => dialect.load().and_then(|dialect| {
+            process(
+                &inputs,
+                &output,
+                &ledger,
+                dialect,
+                snapshot.as_deref(),
+                ids.as_deref(),
+                Some(&metrics),
+            )
+        }),
+        Command::Validate {
+            inputs,
+            dialect,
+            verify_each,
+        } => dialect
+            .load()
+            .and_then(|dialect| validate(&inputs, &dialect, verify_each)),
+        // Journals are always written in the default dialect.
+        Command::Replay {
+            journal,
+            output,
+            ledger,
+        } => process(
+            &[journal],
+            &output,
+            &ledger,
+            Dialect::default(),
+            None,
+            None,
+            None,
+        ),
+        Command::Inspect {
+            inputs,
+            client,
+            ledger,
+            dialect,
+        } => dialect
+            .load()
+            .and_then(|dialect| inspect(&inputs, client, &ledger, dialect)),
+        Command::Balance {
+            inputs,
+            client,
+            at,
+            output,
+            ledger,
+            dialect,
+        } => dialect
+            .load()
+            .and_then(|dialect| balance(&inputs, &output, &ledger, dialect, client, at.into())),
+        Command::Statement {
+            inputs,
+            statement,
+            ledger,
+            dialect,
+        } => dialect
+            .load()
+            .and_then(|dialect| statements(&inputs, &statement, &ledger, dialect)),
+    };
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::from(EXIT_REJECTIONS),
+        Err(e) => {
+            error!("{e:#}");
+            ExitCode::from(EXIT_FATAL)
+        }
+    }
+}
+
+fn open(path: &Path) -> anyhow::Result<File> {
+    File::open(path).map_err(|e| anyhow::anyhow!("Failed to open {}: {e}", path.display()))
+}
+
+/// Expands the command line inputs into named readers. Every file is checked up front so
+/// a missing one fails the run before any transaction is applied, but only opened when
+/// its turn comes. An input is a glob pattern only when no such path exists.
+fn open_inputs(inputs: &[String]) -> anyhow::Result<Vec<(String, Input)>> {
+    let mut opened = Vec::new();
+    for input in inputs {
+        let mut paths = Vec::new();
+        let literal = Path::new(input);
+        if input == "-" {
+            opened.push((String::from("-"), Input::Stdin(io::stdin())));
+            continue;
+        } else if literal.is_dir() {
+            for entry in fs::read_dir(input)? {
+                let path = entry?.path();
+                if path.is_file() {
+                    paths.push(path);
+                }
+            }
+            paths.sort();
+        } else if !literal.exists() && input.contains(['*', '?', '[']) {
+            for path in glob::glob(input)? {
+                paths.push(path?);
+            }
+            anyhow::ensure!(!paths.is_empty(), "No inputs match {input}");
+            paths.sort();
+        } else {
+            fs::metadata(literal).map_err(|e| anyhow::anyhow!("Failed to open {input}: {e}"))?;
+            paths.push(literal.to_path_buf());
+        }
+        for path in paths {
+            opened.push((path.display().to_string(), Input::File { path, file: None }));
+        }
+    }
+    Ok(opened)
+}
+
+/// A command line input. Files are opened on their first read, so a run over many files
+/// only holds the one being read open.
+enum Input {
+    Stdin(io::Stdin),
+    File { path: PathBuf, file: Option<File> },
+}
+
+impl io::Read for Input {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Input::Stdin(stdin) => stdin.read(buf),
+            Input::File { path, file } => {
+                let file = match file {
+                    Some(file) => file,
+                    None => file.insert(File::open(path)?),
+                };
+                file.read(buf)
+            }
+        }
+    }
+}
+
+/// Output files written while transactions are still being applied. Each is written to
+/// a `.tmp` file next to it and only moved into place by [`Outputs::commit`], so a run
+/// that fails, strict or fatal, leaves every existing output untouched.
+#[derive(Default)]
+struct Outputs {
+    staged: Vec<(PathBuf, PathBuf, StagedFile)>,
+}
+
+impl Outputs {
+    /// Creates an output file, compressed when the extension of `path` is `.gz` or
+    /// `.zst`.
+    fn create(&mut self, path: &Path) -> anyhow::Result<StagedFile> {
+        let mut staged = path.as_os_str().to_owned();
+        staged.push(".tmp");
+        let staged = PathBuf::from(staged);
+        let file = File::create(&staged)
+            .map_err(|e| anyhow::anyhow!("Failed to create {}: {e}", staged.display()))?;
+        let encoder = StagedFile(Arc::new(Mutex::new(Some(Encoder::for_path(path, file)?))));
+        self.staged.push((staged, path.to_owned(), encoder.clone()));
+        Ok(encoder)
+    }
+
+    /// Opens the output file, or stdout when there is none.
+    fn writer(&mut self, output: Option<&Path>) -> anyhow::Result<Box<dyn io::Write>> {
+        Ok(match output {
+            Some(path) => Box::new(self.create(path)?),
+            None => Box::new(io::stdout()),
+        })
+    }
+
+    /// Finishes every output and only then moves them into place, so one that fails to
+    /// write leaves every existing output untouched.
+    fn commit(mut self) -> anyhow::Result<()> {
+        for (_, path, file) in &self.staged {
+            file.finish()
+                .map_err(|e| anyhow::anyhow!("Failed to write {}: {e}", path.display()))?;
+        }
+        for (staged, path, _) in std::mem::take(&mut self.staged) {
+            fs::rename(&staged, &path)
+                .map_err(|e| anyhow::anyhow!("Failed to write {}: {e}", path.display()))?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Outputs {
+    fn drop(&mut self) {
+        for (staged, _, _) in &self.staged {
+            let _ = fs::remove_file(staged);
+        }
+    }
+}
+
+/// An output file shared between whatever writes it and [`Outputs`], which finishes it
+/// on commit. Writing after that fails.
+#[derive(Clone)]
+struct StagedFile(Arc<Mutex<Option<Encoder<File>>>>);
+
+impl StagedFile {
+    fn finish(&self) -> io::Result<()> {
+        match self.lock().take() {
+            Some(encoder) => encoder.finish().map(drop),
+            None => Ok(()),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Option<Encoder<File>>> {
+        self.0.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl io::Write for StagedFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self.lock().as_mut() {
+            Some(encoder) => encoder.write(buf),
+            None => Err(io::Error::other("the output was already committed")),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.lock().as_mut() {
+            Some(encoder) => encoder.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A ledger configured by the command line options.
+fn ledger(args: &LedgerArgs, outputs: &mut Outputs) -> anyhow::Result<Ledger> {
+    let mut ledger = Ledger::default()
+        .with_timestamp_tolerance(TimeDelta::seconds(args.timestamp_tolerance.into()))
+        .with_require_open(args.require_open)
+        .with_verify_each(args.verify_each);
+    if let Some(cap) = args.kyc_deposit_cap {
+        ledger = ledger.with_kyc_deposit_cap(cap);
+    }
+    if let Some(path) = &args.dispute_policy {
+        let policy = DisputePolicy::load(path).map_err(|e| {
+            anyhow::anyhow!("Failed to load dispute policy {}: {e}", path.display())
+        })?;
+        ledger = ledger.with_dispute_policy(policy);
+    }
+    if let Some(path) = &args.risk_rules {
+        let rules = RiskRules::load(path)
+            .map_err(|e| anyhow::anyhow!("Failed to load risk rules {}: {e}", path.display()))?;
+        ledger = ledger.with_risk_rules(rules);
+    }
+    if let Some(path) = &args.lock_policy {
+        let policy = LockPolicy::load(path)
+            .map_err(|e| anyhow::anyhow!("Failed to load lock policy {}: {e}", path.display()))?;
+        ledger = ledger.with_lock_policy(policy);
+    }
+    if let Some(path) = &args.blocklist {
+        let blocklist = Blocklist::watch(path, Duration::from_secs(1))
+            .map_err(|e| anyhow::anyhow!("Failed to load blocklist {}: {e}", path.display()))?;
+        ledger = ledger.with_blocklist(blocklist);
+    }
+    if let Some(path) = &args.events {
+        let file = outputs.create(path)?;
+        ledger = ledger.with_observer(JsonLinesObserver::new(BufWriter::new(file)));
+    }
+    if let Some(path) = &args.payouts {
+        ledger = ledger.with_observer(PayoutObserver::new(outputs.create(path)?));
+    }
+    Ok(ledger)
+}
+
+fn process(
+    inputs: &[String],
+    output: &OutputArgs,
+    args: &LedgerArgs,
+    dialect: Dialect,
+    snapshot: Option<&Path>,
+    ids: Option<&Path>,
+    metrics: Option<&MetricsArgs>,
+) -> anyhow::Result<bool> {
+    let inputs = open_inputs(inputs)?;
+    let mut outputs = Outputs::default();
+    let writer = outputs.writer(output.output.as_deref())?;
+    let mut system = LedgerSystem::from_inputs(ledger(args, &mut outputs)?, inputs, writer)
+        .with_format(output.format.into())
+        .with_strict(args.strict)
+        .with_dialect(dialect);
+    if let Some(path) = snapshot {
+        system = system.with_journal(outputs.create(path)?);
+    }
+    if let Some((path, format)) =
+        metrics.and_then(|args| Some((args.metrics.as_ref()?, args.metrics_format)))
+    {
+        system = system.with_metrics(BufWriter::new(outputs.create(path)?), format.into());
+    }
+    if let Some(path) = ids {
+        let map = if path.exists() {
+            IdMap::load(open(path)?)
+                .map_err(|e| anyhow::anyhow!("Failed to load {}: {e}", path.display()))?
+        } else {
+            IdMap::default()
+        };
+        system = system.with_ids(map, outputs.create(path)?);
+    }
+    match system.run() {
+        Ok(summary) => {
+            outputs.commit()?;
+            Ok(summary.is_clean())
+        }
+        Err(ledger_system::Error::Output(e)) => Err(e),
+        Err(e) if e.is_fatal() => Err(e.into()),
+        Err(e) => {
+            error!("{e}");
+            Ok(false)
+        }
+    }
+}
+
+type HistorySystem = LedgerSystem<Input, io::Sink>;
+
+/// Applies the inputs to a ledger that keeps its history, through a system `configure`
+/// can add to. `None` when a strict run stopped early, which has already been logged.
+fn apply_with_history(
+    inputs: &[String],
+    args: &LedgerArgs,
+    dialect: Dialect,
+    configure: impl FnOnce(HistorySystem) -> HistorySystem,
+) -> anyhow::Result<Option<(Ledger, RunSummary)>> {
+    let mut outputs = Outputs::default();
+    let ledger = ledger(args, &mut outputs)?.with_history(true);
+    let system = LedgerSystem::from_inputs(ledger, open_inputs(inputs)?, io::sink())
+        .with_strict(args.strict)
+        .with_dialect(dialect);
+    match configure(system).into_ledger() {
+        Ok(applied) => {
+            outputs.commit()?;
+            Ok(Some(applied))
+        }
+        Err(ledger_system::Error::Output(e)) => Err(e),
+        Err(e) if e.is_fatal() => Err(e.into()),
+        Err(e) => {
+            error!("{e}");
+            Ok(None)
+        }
+    }
+}
+
+fn statements(
+    inputs: &[String],
+    args: &StatementArgs,
+    ledger_args: &LedgerArgs,
+    dialect: Dialect,
+) -> anyhow::Result<bool> {
+    let Some((ledger, summary)) =
+        apply_with_history(inputs, ledger_args, dialect, |system| system)?
+    else {
+        return Ok(false);
+    };
+    let history = ledger.history().expect("history is kept");
+    let clients = match args.client {
+        Some(client) => vec![client],
+        None => history.clients(),
+    };
+    let mut outputs = Outputs::default();
+    let mut writer = outputs.writer(args.output.as_deref())?;
+    match args.format {
+        StatementFormat::Csv => {
+            let lines: Vec<_> = clients
+                .iter()
+                .flat_map(|client| history.statement(*client))
+                .collect();
+            statement::write_csv(writer, &lines)?;
+        }
+        StatementFormat::Text => {
+            for client in clients {
+                statement::write_text(&mut writer, client, &history.statement(client))?;
+            }
+        }
+    }
+    outputs.commit()?;
+    Ok(summary.is_clean())
+}
+
+fn balance(
+    inputs: &[String],
+    output: &OutputArgs,
+    args: &LedgerArgs,
+    dialect: Dialect,
+    client: ClientId,
+    at: PointInTime,
+) -> anyhow::Result<bool> {
+    let Some((ledger, summary)) = apply_with_history(inputs, args, dialect, |system| system)?
+    else {
+        return Ok(false);
+    };
+    let account = ledger
+        .account_at(client, at)
+        .ok_or_else(|| anyhow::anyhow!("Client {client} has no history at that point"))?;
+    let mut outputs = Outputs::default();
+    let writer = outputs.writer(output.output.as_deref())?;
+    match output.format {
+        Format::Csv => {
+            let mut writer = csv::Writer::from_writer(writer);
+            writer.serialize(&account)?;
+            writer.flush()?;
+        }
+        Format::Json => serde_json::to_writer_pretty(writer, &account)?,
+    }
+    outputs.commit()?;
+    Ok(summary.is_clean())
+}
+
+fn validate(inputs: &[String], dialect: &Dialect, verify_each: bool) -> anyhow::Result<bool> {
+    let mut rows = 0u64;
+    let mut rejected = 0u64;
+    let mut ledger = Ledger::default().with_verify_each(verify_each);
+    for (name, input) in open_inputs(inputs)? {
+        let mut reader = TransactionReader::with_dialect(input, dialect.clone());
+        for row in reader.rows() {
+            rows += 1;
+            match row.transaction {
+                Err(transaction_reader::Error::Io(e)) => {
+                    return Err(anyhow::anyhow!("Failed to read {name}: {e}"));
+                }
+                Err(e) => {
+                    rejected += 1;
+                    error!("{name}:{}: {e}", row.line);
+                }
+                Ok(transaction) if verify_each => {
+                    let _ = ledger.process(&transaction);
+                    if let Some(report) = ledger.take_violations() {
+                        return Err(anyhow::anyhow!(
+                            "Ledger verification failed after {name}:{}: {report}",
+                            row.line
+                        ));
+                    }
+                }
+                Ok(_) => {}
+            }
+        }
+    }
+    println!("{rows} row(s), {rejected} rejected");
+    Ok(rejected == 0)
+}
 
-    LedgerSystem::new(Ledger::default(), input_file, io::stdout()).run();
+/// Prints every transaction of the client in input order with its outcome and the
+/// balances after it. Automatic resolves are listed before the client's next applied
+/// transaction; rejected rows, including every member of a rejected batch, show the
+/// balances they left unchanged.
+fn inspect(
+    inputs: &[String],
+    client: ClientId,
+    args: &LedgerArgs,
+    dialect: Dialect,
+) -> anyhow::Result<bool> {
+    let (sender, traced) = mpsc::channel();
+    let trace = move |transaction: &Transaction, error: Option<&ledger_system::Error>| {
+        if transaction.client() == client {
+            let rejection = error.map(ToString::to_string);
+            sender
+                .send((transaction.clone(), rejection))
+                .expect("the receiver outlives the run");
+        }
+    };
+    let Some((ledger, summary)) = apply_with_history(inputs, args, dialect, |system| {
+        system.with_transaction_handler(trace)
+    })?
+    else {
+        return Ok(false);
+    };
+    let history = ledger.history().expect("history is kept");
+    let mut lines = history.statement(client).into_iter().peekable();
+    let mut balances = (Decimal::ZERO, Decimal::ZERO, Decimal::ZERO, false);
+    let mut writer = csv::Writer::from_writer(io::stdout());
+    for (transaction, rejection) in traced.try_iter() {
+        if rejection.is_none() {
+            // History lines before this transaction's own were resolved automatically.
+            let own = (transaction.r#type(), transaction.tx());
+            while let Some(line) = lines.next_if(|line| (line.r#type, line.tx) != own) {
+                balances = (line.available, line.held, line.total, line.locked);
+                writer.serialize(InspectRow::automatic(line))?;
+            }
+            if let Some(line) = lines.next() {
+                balances = (line.available, line.held, line.total, line.locked);
+            }
+        }
+        let row = CsvTransaction::from(&transaction);
+        let (available, held, total, locked) = balances;
+        writer.serialize(InspectRow {
+            r#type: row.r#type,
+            client: row.client,
+            tx: row.tx,
+            amount: row.amount,
+            timestamp: row.timestamp,
+            result: rejection.unwrap_or_else(|| String::from("applied")),
+            available,
+            held,
+            total,
+            locked,
+        })?;
+    }
+    for line in lines {
+        writer.serialize(InspectRow::automatic(line))?;
+    }
+    writer.flush()?;
+    Ok(summary.is_clean())
 }
@@ -0,0 +1,200 @@
+use crate::error::ErrorCode;
+use crate::ledger::Ledger;
+use crate::transaction_type::TransactionType;
+use log::error;
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io;
+use std::io::Write;
+use std::time::Duration;
+
+/// Upper bounds, in seconds, of the processing latency histogram buckets.
+const LATENCY_BUCKETS: [f64; 8] = [1e-6, 1e-5, 1e-4, 1e-3, 1e-2, 0.1, 1.0, 10.0];
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MetricsFormat {
+    #[default]
+    Prometheus,
+    Json,
+}
+
+/// Distribution of how long each row, or each whole batch, took to apply.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Histogram {
+    /// Cumulative count per bucket, one for each of `LATENCY_BUCKETS`.
+    pub buckets: Vec<(f64, u64)>,
+    pub count: u64,
+    pub sum: f64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram {
+            buckets: LATENCY_BUCKETS.iter().map(|&bound| (bound, 0)).collect(),
+            count: 0,
+            sum: 0.0,
+        }
+    }
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let seconds = elapsed.as_secs_f64();
+        for (bound, count) in &mut self.buckets {
+            if seconds <= *bound {
+                *count += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += seconds;
+    }
+}
+
+/// What one run of a [`LedgerSystem`](crate::ledger_system::LedgerSystem) did.
+///
+/// Counters cover the run only; the account gauges describe the ledger once the run is
+/// over, including accounts it already held.
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct Metrics {
+    pub rows_read: u64,
+    /// Rejected rows by the code of the error that rejected them. Every member of a
+    /// rejected batch counts under the code of the member that failed it.
+    pub rows_rejected: BTreeMap<ErrorCode, u64>,
+    pub transactions_applied: BTreeMap<TransactionType, u64>,
+    pub accounts_created: u64,
+    pub accounts_locked: u64,
+    pub open_disputes: u64,
+    /// `None` when the funds held across all accounts add up to more than a `Decimal`
+    /// can represent.
+    pub funds_held: Option<Decimal>,
+    pub processing_seconds: Histogram,
+    pub run_seconds: f64,
+}
+
+impl Metrics {
+    pub(crate) fn rejected(&mut self, code: ErrorCode, rows: u64) {
+        *self.rows_rejected.entry(code).or_default() += rows;
+    }
+
+    pub(crate) fn applied(&mut self, r#type: TransactionType) {
+        *self.transactions_applied.entry(r#type).or_default() += 1;
+    }
+
+    pub(crate) fn observe(&mut self, elapsed: Duration) {
+        self.processing_seconds.observe(elapsed);
+    }
+
+    /// Takes the account gauges from the ledger at the end of the run. `accounts_before`
+    /// is how many accounts the ledger held when the run started.
+    pub(crate) fn finish(&mut self, ledger: &Ledger, accounts_before: usize, run: Duration) {
+        let accounts = ledger.accounts();
+        self.accounts_created = accounts.len().saturating_sub(accounts_before) as u64;
+        self.accounts_locked = accounts.iter().filter(|account| account.locked()).count() as u64;
+        self.open_disputes = accounts
+            .iter()
+            .map(|account| account.open_disputes() as u64)
+            .sum();
+        self.funds_held = accounts.iter().try_fold(Decimal::ZERO, |sum, account| {
+            sum.checked_add(account.held())
+        });
+        if self.funds_held.is_none() {
+            error!("Funds held across all accounts overflow; the gauge is reported as NaN");
+        }
+        self.run_seconds = run.as_secs_f64();
+    }
+
+    /// Writes the metrics and flushes `writer`, so a buffered sink reports its errors
+    /// here rather than losing them when dropped.
+    pub fn write<W: Write>(&self, mut writer: W, format: MetricsFormat) -> io::Result<()> {
+        match format {
+            MetricsFormat::Prometheus => self.write_prometheus(&mut writer)?,
+            MetricsFormat::Json => {
+                serde_json::to_writer_pretty(&mut writer, self).map_err(io::Error::from)?
+            }
+        }
+        writer.flush()
+    }
+
+    /// Writes the metrics in the Prometheus text exposition format.
+    pub fn write_prometheus<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        let w = &mut writer;
+        family(
+            w,
+            "rows_read_total",
+            "counter",
+            "Rows read from the inputs.",
+        )?;
+        writeln!(w, "ledger_rows_read_total {}", self.rows_read)?;
+        family(
+            w,
+            "rows_rejected_total",
+            "counter",
+            "Rows rejected, by error code.",
+        )?;
+        for (code, count) in &self.rows_rejected {
+            writeln!(w, "ledger_rows_rejected_total{{code=\"{code}\"}} {count}")?;
+        }
+        family(
+            w,
+            "transactions_applied_total",
+            "counter",
+            "Transactions applied, by type.",
+        )?;
+        for (r#type, count) in &self.transactions_applied {
+            let name = r#type.name();
+            writeln!(
+                w,
+                "ledger_transactions_applied_total{{type=\"{name}\"}} {count}"
+            )?;
+        }
+        family(
+            w,
+            "accounts_created_total",
+            "counter",
+            "Accounts opened by the run.",
+        )?;
+        writeln!(w, "ledger_accounts_created_total {}", self.accounts_created)?;
+        family(w, "accounts_locked", "gauge", "Locked accounts.")?;
+        writeln!(w, "ledger_accounts_locked {}", self.accounts_locked)?;
+        family(
+            w,
+            "open_disputes",
+            "gauge",
+            "Disputes not yet resolved or charged back.",
+        )?;
+        writeln!(w, "ledger_open_disputes {}", self.open_disputes)?;
+        family(w, "funds_held", "gauge", "Funds held by open disputes.")?;
+        match self.funds_held {
+            Some(held) => writeln!(w, "ledger_funds_held {held}")?,
+            None => writeln!(w, "ledger_funds_held NaN")?,
+        }
+        family(
+            w,
+            "processing_seconds",
+            "histogram",
+            "Time to apply each row or batch.",
+        )?;
+        let histogram = &self.processing_seconds;
+        for (bound, count) in &histogram.buckets {
+            writeln!(
+                w,
+                "ledger_processing_seconds_bucket{{le=\"{bound}\"}} {count}"
+            )?;
+        }
+        writeln!(
+            w,
+            "ledger_processing_seconds_bucket{{le=\"+Inf\"}} {}",
+            histogram.count
+        )?;
+        writeln!(w, "ledger_processing_seconds_sum {}", histogram.sum)?;
+        writeln!(w, "ledger_processing_seconds_count {}", histogram.count)?;
+        family(w, "run_seconds", "gauge", "Duration of the whole run.")?;
+        writeln!(w, "ledger_run_seconds {}", self.run_seconds)
+    }
+}
+
+fn family<W: Write>(writer: &mut W, name: &str, kind: &str, help: &str) -> io::Result<()> {
+    writeln!(writer, "# HELP ledger_{name} {help}")?;
+    writeln!(writer, "# TYPE ledger_{name} {kind}")
+}
@@ -0,0 +1,66 @@
+use crate::event::{Event, Observer};
+use crate::id::{ClientId, TxId};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::io;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Payout {
+    pub client: ClientId,
+    pub tx: TxId,
+    pub amount: Decimal,
+}
+
+pub struct PayoutObserver<W: io::Write> {
+    writer: csv::Writer<W>,
+    /// The first failed write, reported again by [`Observer::flush`].
+    failed: Option<io::Error>,
+}
+
+impl Payout {
+    /// The names of the serialized fields in order, which head every CSV of payouts.
+    pub const FIELDS: [&'static str; 3] = ["client", "tx", "amount"];
+}
+
+impl<W: io::Write> PayoutObserver<W> {
+    pub fn new(writer: W) -> Self {
+        // The header is written up front so that a run without payouts still has one.
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(writer);
+        let failed = writer
+            .write_record(Payout::FIELDS)
+            .err()
+            .map(io::Error::from);
+        PayoutObserver { writer, failed }
+    }
+}
+
+impl<W: io::Write> Observer for PayoutObserver<W> {
+    fn notify(&mut self, event: &Event) {
+        let Event::AccountClosed { client, tx, amount } = event else {
+            return;
+        };
+        let payout = Payout {
+            client: *client,
+            tx: *tx,
+            amount: *amount,
+        };
+        let written = self
+            .writer
+            .serialize(payout)
+            .map_err(io::Error::from)
+            .and_then(|()| self.writer.flush());
+        if let Err(e) = written {
+            log::error!("Failed to write payout: {e}");
+            self.failed.get_or_insert(e);
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self.failed.take() {
+            Some(e) => Err(e),
+            None => self.writer.flush(),
+        }
+    }
+}
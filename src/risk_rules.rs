@@ -0,0 +1,366 @@
+use crate::error::{Coded, ErrorCode};
+use crate::transaction_type::TransactionType;
+use chrono::{DateTime, TimeDelta, Utc};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error(transparent)]
+    Parse(#[from] toml::de::Error),
+    #[error("a rule takes either window or window_transactions")]
+    AmbiguousWindow,
+    #[error(
+        "a rule takes exactly one of max_amount, max_count or max_volume, \
+         and a window unless it is max_amount"
+    )]
+    InvalidRule,
+}
+
+impl Coded for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::Io(_) => ErrorCode::Io,
+            Error::Parse(_) | Error::AmbiguousWindow | Error::InvalidRule => {
+                ErrorCode::InvalidConfig
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Window {
+    /// The last N deposits and withdrawals, including the one being checked.
+    Transactions(usize),
+    /// Untimestamped transactions are never in a time window.
+    Duration(TimeDelta),
+}
+
+impl fmt::Display for Window {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Window::Transactions(count) => write!(f, "{count} transactions"),
+            Window::Duration(duration) => write!(f, "{}s", duration.num_seconds()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rule {
+    MaxAmount {
+        r#type: TransactionType,
+        max: Decimal,
+    },
+    MaxCount {
+        r#type: TransactionType,
+        max: usize,
+        window: Window,
+    },
+    MaxVolume {
+        r#type: TransactionType,
+        max: Decimal,
+        window: Window,
+    },
+}
+
+impl Rule {
+    fn r#type(&self) -> TransactionType {
+        match self {
+            Rule::MaxAmount { r#type, .. }
+            | Rule::MaxCount { r#type, .. }
+            | Rule::MaxVolume { r#type, .. } => *r#type,
+        }
+    }
+
+    fn window(&self) -> Option<Window> {
+        match self {
+            Rule::MaxAmount { .. } => None,
+            Rule::MaxCount { window, .. } | Rule::MaxVolume { window, .. } => Some(*window),
+        }
+    }
+
+    fn is_violated_by(&self, activity: &VecDeque<Activity>, candidate: &Activity) -> bool {
+        if candidate.r#type != self.r#type() {
+            return false;
+        }
+        let in_window = |window: Window| {
+            let recent: Box<dyn Iterator<Item = &Activity>> = match window {
+                Window::Transactions(count) => {
+                    Box::new(activity.iter().rev().take(count.saturating_sub(1)))
+                }
+                Window::Duration(duration) => match candidate.timestamp {
+                    Some(now) => Box::new(activity.iter().rev().filter(move |earlier| {
+                        earlier.timestamp.is_some_and(|at| at > now - duration)
+                    })),
+                    None => Box::new(std::iter::empty()),
+                },
+            };
+            recent.filter(|earlier| earlier.r#type == candidate.r#type)
+        };
+        match self {
+            Rule::MaxAmount { max, .. } => candidate.amount > *max,
+            Rule::MaxCount { max, window, .. } => in_window(*window).count() + 1 > *max,
+            Rule::MaxVolume { max, window, .. } => in_window(*window)
+                .map(|earlier| earlier.amount)
+                .try_fold(candidate.amount, |sum, amount| sum.checked_add(amount))
+                .is_none_or(|volume| volume > *max),
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rule::MaxAmount { r#type, max } => write!(f, "{} amount above {max}", r#type.name()),
+            Rule::MaxCount {
+                r#type,
+                max,
+                window,
+            } => write!(f, "{} count above {max} in {window}", r#type.name()),
+            Rule::MaxVolume {
+                r#type,
+                max,
+                window,
+            } => write!(f, "{} volume above {max} in {window}", r#type.name()),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    #[default]
+    Reject,
+    /// Applied, but reported for review.
+    Flag,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Activity {
+    pub(crate) r#type: TransactionType,
+    pub(crate) amount: Decimal,
+    pub(crate) timestamp: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RiskRules {
+    rules: Vec<(Rule, Action)>,
+}
+
+/// One `[[rules]]` table per rule; the format is described in the README.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    rules: Vec<RuleConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RuleConfig {
+    r#type: TransactionType,
+    max_amount: Option<Decimal>,
+    max_count: Option<usize>,
+    max_volume: Option<Decimal>,
+    window_transactions: Option<usize>,
+    window: Option<u32>,
+    #[serde(default)]
+    action: Action,
+}
+
+impl RuleConfig {
+    fn into_rule(self) -> Result<(Rule, Action), Error> {
+        let window = match (self.window_transactions, self.window) {
+            (Some(count), None) => Some(Window::Transactions(count)),
+            (None, Some(seconds)) => Some(Window::Duration(TimeDelta::seconds(seconds.into()))),
+            (None, None) => None,
+            (Some(_), Some(_)) => return Err(Error::AmbiguousWindow),
+        };
+        let r#type = self.r#type;
+        let rule = match (self.max_amount, self.max_count, self.max_volume, window) {
+            (Some(max), None, None, None) => Rule::MaxAmount { r#type, max },
+            (None, Some(max), None, Some(window)) => Rule::MaxCount {
+                r#type,
+                max,
+                window,
+            },
+            (None, None, Some(max), Some(window)) => Rule::MaxVolume {
+                r#type,
+                max,
+                window,
+            },
+            _ => return Err(Error::InvalidRule),
+        };
+        Ok((rule, self.action))
+    }
+}
+
+impl RiskRules {
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Self::parse(&fs::read_to_string(path)?)
+    }
+
+    fn parse(config: &str) -> Result<Self, Error> {
+        let config: Config = toml::from_str(config)?;
+        config
+            .rules
+            .into_iter()
+            .try_fold(RiskRules::default(), |rules, rule| {
+                let (rule, action) = rule.into_rule()?;
+                Ok(rules.with_rule(rule, action))
+            })
+    }
+
+    pub fn with_rule(mut self, rule: Rule, action: Action) -> Self {
+        self.rules.push((rule, action));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    pub(crate) fn violations(
+        &self,
+        activity: Option<&VecDeque<Activity>>,
+        candidate: &Activity,
+    ) -> Vec<&(Rule, Action)> {
+        let empty = VecDeque::new();
+        let activity = activity.unwrap_or(&empty);
+        self.rules
+            .iter()
+            .filter(|(rule, _)| rule.is_violated_by(activity, candidate))
+            .collect()
+    }
+
+    /// Also forgets whatever no rule window can reach any more.
+    pub(crate) fn record(&self, activity: &mut VecDeque<Activity>, applied: Activity) {
+        let windows = self.rules.iter().filter_map(|(rule, _)| rule.window());
+        let keep = windows
+            .clone()
+            .filter_map(|window| match window {
+                Window::Transactions(count) => Some(count),
+                Window::Duration(_) => None,
+            })
+            .max()
+            .unwrap_or(0);
+        let duration = windows
+            .filter_map(|window| match window {
+                Window::Duration(duration) => Some(duration),
+                Window::Transactions(_) => None,
+            })
+            .max();
+        let now = applied.timestamp;
+        activity.push_back(applied);
+        while activity.len() > keep
+            && let Some(oldest) = activity.front()
+        {
+            let reachable = match (duration, oldest.timestamp, now) {
+                (Some(duration), Some(at), Some(now)) => at > now - duration,
+                // Without a timestamp to measure against, keep what a later one may need.
+                (Some(_), Some(_), None) => true,
+                _ => false,
+            };
+            if reachable {
+                break;
+            }
+            activity.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn withdrawal(amount: i64, minute: u32) -> Activity {
+        Activity {
+            r#type: TransactionType::Withdrawal,
+            amount: Decimal::new(amount, 0),
+            timestamp: format!("2024-01-01T00:{minute:02}:00Z").parse().ok(),
+        }
+    }
+
+    #[test]
+    fn test_parse_rules() {
+        // given ...
+        let config = "[[rules]]\n\
+            type = \"withdrawal\"\n\
+            max_amount = \"500\"\n\
+            [[rules]]\n\
+            type = \"deposit\"\n\
+            max_volume = \"1000\"\n\
+            window = 86400\n\
+            action = \"flag\"\n";
+
+        // when ...
+        let rules = RiskRules::parse(config).unwrap();
+
+        // then ...
+        assert_eq!(
+            rules.rules,
+            vec![
+                (
+                    Rule::MaxAmount {
+                        r#type: TransactionType::Withdrawal,
+                        max: Decimal::new(500, 0)
+                    },
+                    Action::Reject
+                ),
+                (
+                    Rule::MaxVolume {
+                        r#type: TransactionType::Deposit,
+                        max: Decimal::new(1000, 0),
+                        window: Window::Duration(TimeDelta::days(1))
+                    },
+                    Action::Flag
+                ),
+            ]
+        );
+        let error = RiskRules::parse("[[rules]]\ntype = \"deposit\"\nmax_count = 2\n").unwrap_err();
+        assert!(matches!(error, Error::InvalidRule));
+        assert_eq!(error.code(), ErrorCode::InvalidConfig);
+    }
+
+    #[test]
+    fn test_windows() {
+        // given ...
+        let count = Rule::MaxCount {
+            r#type: TransactionType::Withdrawal,
+            max: 2,
+            window: Window::Transactions(3),
+        };
+        let volume = Rule::MaxVolume {
+            r#type: TransactionType::Withdrawal,
+            max: Decimal::new(100, 0),
+            window: Window::Duration(TimeDelta::minutes(10)),
+        };
+        let rules = RiskRules::default()
+            .with_rule(count.clone(), Action::Reject)
+            .with_rule(volume.clone(), Action::Reject);
+        let mut activity = VecDeque::new();
+        rules.record(&mut activity, withdrawal(60, 0));
+        rules.record(&mut activity, withdrawal(30, 5));
+
+        // when ...
+        let third = withdrawal(5, 8);
+        let later = withdrawal(60, 12);
+
+        // then ...
+        assert!(count.is_violated_by(&activity, &third));
+        assert!(!volume.is_violated_by(&activity, &third));
+        assert!(volume.is_violated_by(&activity, &withdrawal(20, 8)));
+        assert!(!volume.is_violated_by(&activity, &later));
+        rules.record(&mut activity, later);
+        assert_eq!(activity.len(), 3);
+        rules.record(&mut activity, withdrawal(1, 30));
+        assert_eq!(activity.len(), 3);
+    }
+}
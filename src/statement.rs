@@ -0,0 +1,130 @@
+use crate::account_state::AccountState;
+use crate::id::{ClientId, TxId};
+use crate::transaction::{Transaction, rfc3339};
+use crate::transaction_type::TransactionType;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use serde::Serialize;
+use std::io;
+use std::io::Write;
+
+/// One transaction on a client's statement and the balances right after it. For
+/// disputes, resolves and chargebacks `amount` is the amount of the disputed transaction.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatementLine {
+    pub client: ClientId,
+    pub timestamp: Option<DateTime<Utc>>,
+    pub r#type: TransactionType,
+    pub tx: TxId,
+    pub amount: Decimal,
+    pub available: Decimal,
+    pub held: Decimal,
+    pub total: Decimal,
+    pub locked: bool,
+}
+
+impl StatementLine {
+    pub fn new(transaction: &Transaction, amount: Decimal, account: &AccountState) -> Self {
+        StatementLine {
+            client: account.client(),
+            timestamp: transaction.timestamp(),
+            r#type: transaction.r#type(),
+            tx: transaction.tx(),
+            amount,
+            available: account.available(),
+            held: account.held(),
+            total: account.total(),
+            locked: account.locked(),
+        }
+    }
+}
+
+/// Writes statement lines as one CSV, with a header row once for all clients.
+pub fn write_csv<W>(writer: W, lines: &[StatementLine]) -> anyhow::Result<()>
+where
+    W: Write,
+{
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    for line in lines {
+        csv_writer.serialize(line)?;
+    }
+    csv_writer.flush()?;
+    Ok(())
+}
+
+/// Writes one client's statement as aligned plain text.
+pub fn write_text<W>(mut writer: W, client: ClientId, lines: &[StatementLine]) -> io::Result<()>
+where
+    W: Write,
+{
+    writeln!(writer, "Statement for client {client}")?;
+    writeln!(
+        writer,
+        "{:<20}  {:<10}  {:>10}  {:>14}  {:>14}  {:>14}  {:>14}",
+        "timestamp", "type", "tx", "amount", "available", "held", "total"
+    )?;
+    for line in lines {
+        let timestamp = line.timestamp.as_ref().map(rfc3339).unwrap_or_default();
+        writeln!(
+            writer,
+            "{:<20}  {:<10}  {:>10}  {:>14}  {:>14}  {:>14}  {:>14}{}",
+            timestamp,
+            line.r#type.name(),
+            line.tx.to_string(),
+            line.amount.to_string(),
+            line.available.to_string(),
+            line.held.to_string(),
+            line.total.to_string(),
+            if line.locked { "  locked" } else { "" }
+        )?;
+    }
+    writeln!(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ledger::Ledger;
+    use crate::transaction::{DepositTransaction, DisputeTransaction, ResolveTransaction};
+
+    #[test]
+    fn test_statement_tracks_running_balances() {
+        // given ...
+        let mut ledger = Ledger::default().with_history(true);
+        let transactions = [
+            Transaction::Deposit(DepositTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                amount: Decimal::new(100, 0),
+                timestamp: None,
+            }),
+            Transaction::Dispute(DisputeTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                timestamp: None,
+            }),
+            Transaction::Resolve(ResolveTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                timestamp: None,
+            }),
+        ];
+        for transaction in &transactions {
+            ledger.process(transaction).unwrap();
+        }
+        let lines = ledger.history().unwrap().statement(ClientId(1));
+        let mut output = Vec::new();
+
+        // when ...
+        write_csv(&mut output, &lines).unwrap();
+
+        // then ...
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "client,timestamp,type,tx,amount,available,held,total,locked\n\
+            1,,deposit,1,100,100,0,100,false\n\
+            1,,dispute,1,100,0,100,100,false\n\
+            1,,resolve,1,100,100,0,100,false\n"
+        );
+    }
+}
@@ -1,26 +1,50 @@
+use crate::id::{ClientId, TxId};
 use crate::transaction::{DepositTransaction, WithdrawalTransaction};
+use crate::transaction_type::TransactionType;
+use chrono::{DateTime, Utc};
 use rust_decimal::Decimal;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum StoredTransaction {
     Deposit(StoredDepositTransaction),
     Withdrawal(StoredWithdrawalTransaction),
 }
 
 impl StoredTransaction {
-    pub fn tx(&self) -> u32 {
+    pub fn tx(&self) -> TxId {
         match self {
             StoredTransaction::Deposit(deposit) => deposit.tx,
             StoredTransaction::Withdrawal(withdrawal) => withdrawal.tx,
         }
     }
 
-    pub fn client(&self) -> u16 {
+    pub fn client(&self) -> ClientId {
         match self {
             StoredTransaction::Deposit(deposit) => deposit.client,
             StoredTransaction::Withdrawal(withdrawal) => withdrawal.client,
         }
     }
+
+    pub fn r#type(&self) -> TransactionType {
+        match self {
+            StoredTransaction::Deposit(_) => TransactionType::Deposit,
+            StoredTransaction::Withdrawal(_) => TransactionType::Withdrawal,
+        }
+    }
+
+    pub fn amount(&self) -> Decimal {
+        match self {
+            StoredTransaction::Deposit(deposit) => deposit.amount,
+            StoredTransaction::Withdrawal(withdrawal) => withdrawal.amount,
+        }
+    }
+
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        match self {
+            StoredTransaction::Deposit(deposit) => deposit.timestamp,
+            StoredTransaction::Withdrawal(withdrawal) => withdrawal.timestamp,
+        }
+    }
 }
 
 impl From<&DepositTransaction> for StoredTransaction {
@@ -35,11 +59,12 @@ impl From<&WithdrawalTransaction> for StoredTransaction {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct StoredDepositTransaction {
-    pub tx: u32,
-    pub client: u16,
+    pub tx: TxId,
+    pub client: ClientId,
     pub amount: Decimal,
+    pub timestamp: Option<DateTime<Utc>>,
 }
 
 impl From<&DepositTransaction> for StoredDepositTransaction {
@@ -48,15 +73,17 @@ impl From<&DepositTransaction> for StoredDepositTransaction {
             tx: deposit.tx,
             client: deposit.client,
             amount: deposit.amount,
+            timestamp: deposit.timestamp,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct StoredWithdrawalTransaction {
-    pub tx: u32,
-    pub client: u16,
+    pub tx: TxId,
+    pub client: ClientId,
     pub amount: Decimal,
+    pub timestamp: Option<DateTime<Utc>>,
 }
 
 impl From<&WithdrawalTransaction> for StoredWithdrawalTransaction {
@@ -65,6 +92,7 @@ impl From<&WithdrawalTransaction> for StoredWithdrawalTransaction {
             tx: withdrawal.tx,
             client: withdrawal.client,
             amount: withdrawal.amount,
+            timestamp: withdrawal.timestamp,
         }
     }
 }
@@ -1,17 +1,87 @@
+use crate::error::{Coded, ErrorCode};
+use crate::id::{ClientId, TxId};
 use crate::transaction_type::TransactionType;
+use chrono::{DateTime, SecondsFormat, Utc};
 use rust_decimal::{Decimal, RoundingStrategy};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Error, PartialEq)]
+pub enum Error {
+    #[error("Deposit transaction must have an amount")]
+    DepositWithoutAmount { client: ClientId, tx: TxId },
+    #[error("Withdrawal transaction must have an amount")]
+    WithdrawalWithoutAmount { client: ClientId, tx: TxId },
+}
+
+impl Error {
+    pub fn client(&self) -> ClientId {
+        match self {
+            Error::DepositWithoutAmount { client, .. }
+            | Error::WithdrawalWithoutAmount { client, .. } => *client,
+        }
+    }
+
+    pub fn tx(&self) -> TxId {
+        match self {
+            Error::DepositWithoutAmount { tx, .. } | Error::WithdrawalWithoutAmount { tx, .. } => {
+                *tx
+            }
+        }
+    }
+}
+
+impl Coded for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::DepositWithoutAmount { .. } | Error::WithdrawalWithoutAmount { .. } => {
+                ErrorCode::MissingAmount
+            }
+        }
+    }
+}
+
+/// Formats a transaction timestamp the way it is accepted on input.
+pub fn rfc3339(timestamp: &DateTime<Utc>) -> String {
+    timestamp.to_rfc3339_opts(SecondsFormat::AutoSi, true)
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 pub struct CsvTransaction {
     pub r#type: TransactionType,
-    pub client: u16,
-    pub tx: u32,
+    pub client: ClientId,
+    pub tx: TxId,
     pub amount: Option<Decimal>,
+    /// Optional RFC 3339 time the partner recorded the transaction at.
+    #[serde(default)]
+    pub timestamp: Option<DateTime<Utc>>,
+    /// Rows sharing a batch are applied all-or-nothing. Read from the raw record by
+    /// [`TransactionReader::rows`](crate::transaction_reader::TransactionReader::rows);
+    /// declared here so rows that omit the trailing column still deserialize.
+    #[serde(default, skip_serializing)]
+    pub batch: Option<String>,
+}
+
+impl From<&Transaction> for CsvTransaction {
+    fn from(transaction: &Transaction) -> Self {
+        let amount = match transaction {
+            Transaction::Deposit(deposit) => Some(deposit.amount),
+            Transaction::Withdrawal(withdrawal) => Some(withdrawal.amount),
+            _ => None,
+        };
+        CsvTransaction {
+            r#type: transaction.r#type(),
+            client: transaction.client(),
+            tx: transaction.tx(),
+            amount,
+            timestamp: transaction.timestamp(),
+            batch: None,
+        }
+    }
 }
 
 impl TryFrom<CsvTransaction> for Transaction {
-    type Error = String;
+    type Error = Error;
 
     fn try_from(csv: CsvTransaction) -> Result<Self, Self::Error> {
         match csv.r#type {
@@ -21,9 +91,13 @@ impl TryFrom<CsvTransaction> for Transaction {
                         client: csv.client,
                         tx: csv.tx,
                         amount: amount.round_dp_with_strategy(4, RoundingStrategy::ToZero),
+                        timestamp: csv.timestamp,
                     }))
                 } else {
-                    Err("Deposit transaction must have an amount".to_string())
+                    Err(Error::DepositWithoutAmount {
+                        client: csv.client,
+                        tx: csv.tx,
+                    })
                 }
             }
             TransactionType::Withdrawal => {
@@ -32,22 +106,39 @@ impl TryFrom<CsvTransaction> for Transaction {
                         client: csv.client,
                         tx: csv.tx,
                         amount: amount.round_dp_with_strategy(4, RoundingStrategy::ToZero),
+                        timestamp: csv.timestamp,
                     }))
                 } else {
-                    Err("Withdrawal transaction must have an amount".to_string())
+                    Err(Error::WithdrawalWithoutAmount {
+                        client: csv.client,
+                        tx: csv.tx,
+                    })
                 }
             }
             TransactionType::Dispute => Ok(Transaction::Dispute(DisputeTransaction {
                 client: csv.client,
                 tx: csv.tx,
+                timestamp: csv.timestamp,
             })),
             TransactionType::Resolve => Ok(Transaction::Resolve(ResolveTransaction {
                 client: csv.client,
                 tx: csv.tx,
+                timestamp: csv.timestamp,
             })),
             TransactionType::Chargeback => Ok(Transaction::Chargeback(ChargebackTransaction {
                 client: csv.client,
                 tx: csv.tx,
+                timestamp: csv.timestamp,
+            })),
+            TransactionType::Open => Ok(Transaction::Open(OpenTransaction {
+                client: csv.client,
+                tx: csv.tx,
+                timestamp: csv.timestamp,
+            })),
+            TransactionType::Close => Ok(Transaction::Close(CloseTransaction {
+                client: csv.client,
+                tx: csv.tx,
+                timestamp: csv.timestamp,
             })),
         }
     }
@@ -60,36 +151,112 @@ pub enum Transaction {
     Dispute(DisputeTransaction),
     Resolve(ResolveTransaction),
     Chargeback(ChargebackTransaction),
+    Open(OpenTransaction),
+    Close(CloseTransaction),
+}
+
+impl Transaction {
+    pub fn r#type(&self) -> TransactionType {
+        match self {
+            Transaction::Deposit(_) => TransactionType::Deposit,
+            Transaction::Withdrawal(_) => TransactionType::Withdrawal,
+            Transaction::Dispute(_) => TransactionType::Dispute,
+            Transaction::Resolve(_) => TransactionType::Resolve,
+            Transaction::Chargeback(_) => TransactionType::Chargeback,
+            Transaction::Open(_) => TransactionType::Open,
+            Transaction::Close(_) => TransactionType::Close,
+        }
+    }
+
+    pub fn client(&self) -> ClientId {
+        match self {
+            Transaction::Deposit(deposit) => deposit.client,
+            Transaction::Withdrawal(withdrawal) => withdrawal.client,
+            Transaction::Dispute(dispute) => dispute.client,
+            Transaction::Resolve(resolve) => resolve.client,
+            Transaction::Chargeback(chargeback) => chargeback.client,
+            Transaction::Open(open) => open.client,
+            Transaction::Close(close) => close.client,
+        }
+    }
+
+    pub fn tx(&self) -> TxId {
+        match self {
+            Transaction::Deposit(deposit) => deposit.tx,
+            Transaction::Withdrawal(withdrawal) => withdrawal.tx,
+            Transaction::Dispute(dispute) => dispute.tx,
+            Transaction::Resolve(resolve) => resolve.tx,
+            Transaction::Chargeback(chargeback) => chargeback.tx,
+            Transaction::Open(open) => open.tx,
+            Transaction::Close(close) => close.tx,
+        }
+    }
+
+    pub fn timestamp(&self) -> Option<DateTime<Utc>> {
+        match self {
+            Transaction::Deposit(deposit) => deposit.timestamp,
+            Transaction::Withdrawal(withdrawal) => withdrawal.timestamp,
+            Transaction::Dispute(dispute) => dispute.timestamp,
+            Transaction::Resolve(resolve) => resolve.timestamp,
+            Transaction::Chargeback(chargeback) => chargeback.timestamp,
+            Transaction::Open(open) => open.timestamp,
+            Transaction::Close(close) => close.timestamp,
+        }
+    }
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct DepositTransaction {
-    pub client: u16,
-    pub tx: u32,
+    pub client: ClientId,
+    pub tx: TxId,
     pub amount: Decimal,
+    pub timestamp: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct WithdrawalTransaction {
-    pub client: u16,
-    pub tx: u32,
+    pub client: ClientId,
+    pub tx: TxId,
     pub amount: Decimal,
+    pub timestamp: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct DisputeTransaction {
-    pub client: u16,
-    pub tx: u32,
+    pub client: ClientId,
+    pub tx: TxId,
+    pub timestamp: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct ResolveTransaction {
-    pub client: u16,
-    pub tx: u32,
+    pub client: ClientId,
+    pub tx: TxId,
+    pub timestamp: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub struct ChargebackTransaction {
-    pub client: u16,
-    pub tx: u32,
+    pub client: ClientId,
+    pub tx: TxId,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+/// Opens the client's account, or activates it when it is pending KYC or frozen. `tx`
+/// only identifies the row; it is not stored and cannot be disputed, but no other
+/// transaction may reuse it.
+#[derive(Debug, PartialEq, Clone)]
+pub struct OpenTransaction {
+    pub client: ClientId,
+    pub tx: TxId,
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+/// Closes the client's account and pays out its available balance. `tx` identifies the
+/// payout; like an open, it is not stored and cannot be disputed.
+#[derive(Debug, PartialEq, Clone)]
+pub struct CloseTransaction {
+    pub client: ClientId,
+    pub tx: TxId,
+    pub timestamp: Option<DateTime<Utc>>,
 }
@@ -0,0 +1,194 @@
+use rand::Rng;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use rust_decimal::Decimal;
+use std::io;
+use std::io::Write;
+
+// Disputes overwhelmingly target recent deposits, so only a bounded window of
+// them is remembered. This keeps memory flat for 100M row workloads.
+const DISPUTABLE_WINDOW: usize = 1 << 16;
+
+#[derive(Debug, Clone)]
+pub struct GeneratorConfig {
+    pub clients: u64,
+    pub transactions: u64,
+    pub dispute_rate: f64,
+    pub resolve_rate: f64,
+    pub chargeback_rate: f64,
+    pub malformed_rate: f64,
+    pub seed: u64,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        GeneratorConfig {
+            clients: 1000,
+            transactions: 10_000,
+            dispute_rate: 0.01,
+            resolve_rate: 0.007,
+            chargeback_rate: 0.002,
+            malformed_rate: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+/// Writes a pseudo-random, but reproducible for a given seed, stream of CSV rows in the
+/// format accepted by [`TransactionReader`](crate::transaction_reader::TransactionReader).
+pub struct TransactionGenerator {
+    config: GeneratorConfig,
+    rng: ChaCha8Rng,
+    next_tx: u64,
+    disputable: Vec<(u64, u64)>,
+    disputed: Vec<(u64, u64)>,
+}
+
+impl TransactionGenerator {
+    pub fn new(config: GeneratorConfig) -> Self {
+        let rng = ChaCha8Rng::seed_from_u64(config.seed);
+        TransactionGenerator {
+            config,
+            rng,
+            next_tx: 1,
+            disputable: Vec::new(),
+            disputed: Vec::new(),
+        }
+    }
+
+    pub fn write_to<W>(mut self, writer: W) -> io::Result<()>
+    where
+        W: Write,
+    {
+        let mut writer = io::BufWriter::new(writer);
+        writeln!(writer, "type,client,tx,amount")?;
+        for _ in 0..self.config.transactions {
+            self.write_row(&mut writer)?;
+        }
+        writer.flush()
+    }
+
+    fn write_row<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        if self.rng.random_bool(self.config.malformed_rate) {
+            return self.write_malformed(writer);
+        }
+        if !self.disputed.is_empty() {
+            if self.rng.random_bool(self.config.chargeback_rate) {
+                let (client, tx) = self.take_disputed();
+                return writeln!(writer, "chargeback,{client},{tx},");
+            }
+            if self.rng.random_bool(self.config.resolve_rate) {
+                let (client, tx) = self.take_disputed();
+                return writeln!(writer, "resolve,{client},{tx},");
+            }
+        }
+        if !self.disputable.is_empty() && self.rng.random_bool(self.config.dispute_rate) {
+            let index = self.rng.random_range(0..self.disputable.len());
+            let (client, tx) = self.disputable.swap_remove(index);
+            self.disputed.push((client, tx));
+            return writeln!(writer, "dispute,{client},{tx},");
+        }
+
+        let client = self.client();
+        let tx = self.tx();
+        let amount = self.amount();
+        if self.rng.random_bool(0.6) {
+            self.remember_deposit(client, tx);
+            writeln!(writer, "deposit,{client},{tx},{amount}")
+        } else {
+            writeln!(writer, "withdrawal,{client},{tx},{amount}")
+        }
+    }
+
+    fn write_malformed<W: Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        let client = self.client();
+        let tx = self.tx();
+        match self.rng.random_range(0..5) {
+            0 => writeln!(writer, "deposit,{client},{tx},"),
+            1 => writeln!(writer, "transfer,{client},{tx},1.0"),
+            2 => writeln!(writer, "withdrawal,{client},{tx},lots"),
+            3 => writeln!(writer, "deposit,{client}"),
+            _ => writeln!(writer, "deposit,-{client},{tx},1.0"),
+        }
+    }
+
+    fn client(&mut self) -> u64 {
+        self.rng.random_range(1..=self.config.clients.max(1))
+    }
+
+    fn tx(&mut self) -> u64 {
+        let tx = self.next_tx;
+        self.next_tx = self.next_tx.wrapping_add(1);
+        tx
+    }
+
+    fn amount(&mut self) -> Decimal {
+        Decimal::new(self.rng.random_range(1..=10_000_000), 4)
+    }
+
+    fn remember_deposit(&mut self, client: u64, tx: u64) {
+        if self.disputable.len() < DISPUTABLE_WINDOW {
+            self.disputable.push((client, tx));
+        } else {
+            let index = self.rng.random_range(0..DISPUTABLE_WINDOW);
+            self.disputable[index] = (client, tx);
+        }
+    }
+
+    fn take_disputed(&mut self) -> (u64, u64) {
+        let index = self.rng.random_range(0..self.disputed.len());
+        self.disputed.swap_remove(index)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transaction_reader::TransactionReader;
+    use std::io::Cursor;
+
+    fn generate(config: GeneratorConfig) -> Vec<u8> {
+        let mut output = Vec::new();
+        TransactionGenerator::new(config)
+            .write_to(&mut output)
+            .unwrap();
+        output
+    }
+
+    #[test]
+    fn test_generator_is_reproducible() {
+        // given ...
+        let config = GeneratorConfig {
+            seed: 42,
+            malformed_rate: 0.05,
+            ..GeneratorConfig::default()
+        };
+
+        // when ...
+        let first = generate(config.clone());
+        let second = generate(config);
+
+        // then ...
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn test_generated_rows_are_readable() {
+        // given ...
+        let config = GeneratorConfig {
+            transactions: 5_000,
+            dispute_rate: 0.1,
+            resolve_rate: 0.05,
+            chargeback_rate: 0.05,
+            ..GeneratorConfig::default()
+        };
+        let output = generate(config);
+
+        // when ...
+        let mut reader = TransactionReader::new(Cursor::new(output));
+        let count = reader.iter().count();
+
+        // then ...
+        assert_eq!(count, 5_000);
+    }
+}
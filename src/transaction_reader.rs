@@ -1,9 +1,70 @@
+use crate::compression::Decoder;
+use crate::dialect::Dialect;
+use crate::error::{Coded, ErrorCode};
+use crate::id::IdMap;
+use crate::transaction;
 use crate::transaction::{CsvTransaction, Transaction};
+use csv::StringRecord;
 use log::error;
+use std::borrow::Cow;
 use std::io;
+use thiserror::Error;
+use tracing::trace_span;
 
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The input could not be read, or did not decompress; nothing after it is readable.
+    #[error("Failed to read input: {0}")]
+    Io(io::Error),
+    #[error("{0}")]
+    Csv(csv::Error),
+    #[error("{source}")]
+    Invalid {
+        line: u64,
+        source: transaction::Error,
+    },
+}
+
+impl Error {
+    /// The input line the rejected row started on, when known.
+    pub fn line(&self) -> Option<u64> {
+        match self {
+            Error::Io(_) => None,
+            Error::Csv(e) => e.position().map(|position| position.line()),
+            Error::Invalid { line, .. } => Some(*line),
+        }
+    }
+}
+
+impl From<csv::Error> for Error {
+    fn from(e: csv::Error) -> Self {
+        if e.is_io_error() {
+            Error::Io(e.into())
+        } else {
+            Error::Csv(e)
+        }
+    }
+}
+
+impl Coded for Error {
+    fn code(&self) -> ErrorCode {
+        match self {
+            Error::Io(_) => ErrorCode::Io,
+            Error::Csv(e) => match e.kind() {
+                csv::ErrorKind::Io(_) => ErrorCode::Io,
+                csv::ErrorKind::Deserialize { .. } => ErrorCode::InvalidField,
+                _ => ErrorCode::MalformedRow,
+            },
+            Error::Invalid { source, .. } => source.code(),
+        }
+    }
+}
+
+/// Reads transactions from CSV, transparently decompressing gzip or zstd input.
 pub struct TransactionReader<R> {
-    csv_reader: csv::Reader<R>,
+    csv_reader: csv::Reader<Decoder<io::BufReader<R>>>,
+    dialect: Dialect,
+    ids: Option<IdMap>,
 }
 
 impl<R> TransactionReader<R>
@@ -11,29 +72,181 @@ where
     R: io::Read,
 {
     pub fn new(reader: R) -> Self {
-        let csv_reader = csv::ReaderBuilder::new()
-            .has_headers(true)
-            .trim(csv::Trim::All)
-            .flexible(true)
-            .from_reader(reader);
-        TransactionReader { csv_reader }
+        Self::with_dialect(reader, Dialect::default())
+    }
+
+    pub fn with_dialect(reader: R, dialect: Dialect) -> Self {
+        let csv_reader = dialect
+            .reader_builder()
+            .from_reader(Decoder::new(io::BufReader::new(reader)));
+        TransactionReader {
+            csv_reader,
+            dialect,
+            ids: None,
+        }
+    }
+
+    /// Treats the `client` and `tx` columns as partner string ids and replaces them with
+    /// the internal ids assigned by `ids`. Take the updated map back with
+    /// [`TransactionReader::into_ids`] once the input is read.
+    pub fn with_ids(mut self, ids: IdMap) -> Self {
+        self.ids = Some(ids);
+        self
+    }
+
+    pub fn into_ids(self) -> Option<IdMap> {
+        self.ids
     }
 
     pub fn iter(&mut self) -> impl Iterator<Item = Transaction> {
-        self.csv_reader
-            .deserialize::<CsvTransaction>()
+        self.results()
             .filter_map(|row| row.inspect_err(|e| error!("{e}")).ok())
-            .filter_map(|row| {
-                Transaction::try_from(row)
-                    .inspect_err(|e| error!("{e}"))
-                    .ok()
+    }
+
+    /// Like [`TransactionReader::iter`], but hands rejected rows to the caller instead of
+    /// logging them.
+    pub fn results(&mut self) -> impl Iterator<Item = Result<Transaction, Error>> {
+        self.rows().map(|row| row.transaction)
+    }
+
+    /// Every row along with the batch it belongs to, taken from the optional `batch`
+    /// column. The batch is read from the raw record so that malformed rows still
+    /// report which batch they would have belonged to. A header row that cannot be read
+    /// is the only row, and an [`Error::Io`] row ends the input.
+    pub fn rows(&mut self) -> impl Iterator<Item = Row> {
+        let dialect = &self.dialect;
+        let (headers, unreadable) = if dialect.has_headers {
+            match self.csv_reader.headers() {
+                Ok(headers) => (dialect.canonical_headers(Some(headers)), None),
+                Err(e) => (None, Some(e)),
+            }
+        } else {
+            (dialect.canonical_headers(None), None)
+        };
+        let unreadable = unreadable.map(|e| Row {
+            line: e.position().map_or(1, |position| position.line()),
+            batch: None,
+            transaction: Err(e.into()),
+        });
+        let records = unreadable.is_none().then(|| self.csv_reader.records());
+        let column = |name| {
+            headers
+                .as_ref()
+                .and_then(|headers| headers.iter().position(|header| header == name))
+        };
+        let batch_column = column("batch");
+        let type_column = column("type").filter(|_| dialect.rewrites_types());
+        let ids = &mut self.ids;
+        let (client_column, tx_column, reference_column) = match ids {
+            Some(_) => (column("client"), column("tx"), column("type")),
+            None => (None, None, None),
+        };
+        let rewrites = type_column.is_some() || client_column.is_some() || tx_column.is_some();
+        let rows = records.into_iter().flatten().map(move |record| {
+            let record = match record {
+                Ok(record) => record,
+                Err(e) => {
+                    return Row {
+                        line: e.position().map_or(0, |position| position.line()),
+                        batch: None,
+                        transaction: Err(e.into()),
+                    };
+                }
+            };
+            let line = record.position().map_or(0, |position| position.line());
+            let _parse = trace_span!("parse", line).entered();
+            let batch = batch_column
+                .and_then(|column| record.get(column))
+                .filter(|batch| !batch.is_empty())
+                .map(String::from);
+            // Disputes, resolves and chargebacks only look up the ids they reference, and
+            // closes the client they reference, so unknown references are not assigned
+            // and saved.
+            let r#type = reference_column
+                .and_then(|column| record.get(column))
+                .map(|r#type| dialect.normalize_type(r#type));
+            let tx_reference = r#type
+                .as_deref()
+                .is_some_and(|r#type| matches!(r#type, "dispute" | "resolve" | "chargeback"));
+            let client_reference = tx_reference || r#type.as_deref() == Some("close");
+            let record = if rewrites {
+                rewrite(record, |index, field| {
+                    if Some(index) == type_column {
+                        return dialect.normalize_type(field);
+                    }
+                    match ids.as_mut().filter(|_| !field.is_empty()) {
+                        Some(ids) if Some(index) == client_column && client_reference => {
+                            Cow::Owned(
+                                ids.find_client(field)
+                                    .unwrap_or(IdMap::UNKNOWN_CLIENT)
+                                    .to_string(),
+                            )
+                        }
+                        Some(ids) if Some(index) == client_column => {
+                            Cow::Owned(ids.client(field).to_string())
+                        }
+                        Some(ids) if Some(index) == tx_column && tx_reference => {
+                            Cow::Owned(ids.find_tx(field).unwrap_or(IdMap::UNKNOWN_TX).to_string())
+                        }
+                        Some(ids) if Some(index) == tx_column => {
+                            Cow::Owned(ids.tx(field).to_string())
+                        }
+                        _ => Cow::Borrowed(field),
+                    }
+                })
+            } else {
+                record
+            };
+            let transaction = record
+                .deserialize::<CsvTransaction>(headers.as_ref())
+                .map_err(Error::from)
+                .and_then(|row| {
+                    Transaction::try_from(row).map_err(|source| Error::Invalid { line, source })
+                });
+            Row {
+                line,
+                batch,
+                transaction,
+            }
+        });
+        unreadable
+            .into_iter()
+            .chain(rows)
+            .scan(false, |ended, row| {
+                (!*ended).then(|| {
+                    *ended = matches!(row.transaction, Err(Error::Io(_)));
+                    row
+                })
             })
     }
 }
 
+/// Rebuilds a record field by field, keeping its position for error reporting.
+fn rewrite<F>(record: StringRecord, mut field: F) -> StringRecord
+where
+    F: FnMut(usize, &str) -> Cow<'_, str>,
+{
+    let mut rewritten: StringRecord = record
+        .iter()
+        .enumerate()
+        .map(|(index, value)| field(index, value))
+        .collect();
+    rewritten.set_position(record.position().cloned());
+    rewritten
+}
+
+#[derive(Debug)]
+pub struct Row {
+    pub line: u64,
+    pub batch: Option<String>,
+    pub transaction: Result<Transaction, Error>,
+}
+
 #[cfg(test)]
 mod tests {
+    use super::Error;
     use super::*;
+    use crate::id::{ClientId, TxId};
     use crate::transaction::*;
     use rust_decimal::Decimal;
     use std::io::Cursor;
@@ -58,19 +271,184 @@ mod tests {
             transactions,
             vec![
                 Transaction::Deposit(DepositTransaction {
-                    client: 1,
-                    tx: 1,
+                    client: ClientId(1),
+                    tx: TxId(1),
                     amount: Decimal::new(10, 1),
+                    timestamp: None,
                 }),
                 Transaction::Withdrawal(WithdrawalTransaction {
-                    client: 2,
-                    tx: 2,
+                    client: ClientId(2),
+                    tx: TxId(2),
                     amount: Decimal::new(20, 1),
+                    timestamp: None,
+                }),
+                Transaction::Dispute(DisputeTransaction {
+                    client: ClientId(3),
+                    tx: TxId(3),
+                    timestamp: None,
+                }),
+                Transaction::Resolve(ResolveTransaction {
+                    client: ClientId(4),
+                    tx: TxId(4),
+                    timestamp: None,
+                }),
+                Transaction::Chargeback(ChargebackTransaction {
+                    client: ClientId(5),
+                    tx: TxId(5),
+                    timestamp: None,
                 }),
-                Transaction::Dispute(DisputeTransaction { client: 3, tx: 3 }),
-                Transaction::Resolve(ResolveTransaction { client: 4, tx: 4 }),
-                Transaction::Chargeback(ChargebackTransaction { client: 5, tx: 5 }),
             ]
         )
     }
+
+    #[test]
+    fn test_transaction_reader_results() {
+        // given ...
+        let data = "type, client, tx, amount\n\
+        deposit, 1, 1, 1.0\n\
+        deposit, 1, 2,\n\
+        refund, 1, 3, 1.0\n";
+        let cursor = Cursor::new(data);
+
+        // when ...
+        let mut reader = TransactionReader::new(cursor);
+        let results: Vec<Result<Transaction, Error>> = reader.results().collect();
+
+        // then ...
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert_eq!(results[1].as_ref().unwrap_err().line(), Some(3));
+        assert_eq!(
+            results[1].as_ref().unwrap_err().to_string(),
+            "Deposit transaction must have an amount"
+        );
+        assert_eq!(results[2].as_ref().unwrap_err().line(), Some(4));
+    }
+
+    #[test]
+    fn test_transaction_reader_rows_with_batches() {
+        // given ...
+        let data = "type, client, tx, amount, batch\n\
+        deposit, 1, 1, 1.0, a\n\
+        withdrawal, 1, 2, , a\n\
+        deposit, 2, 3, 1.0,\n\
+        deposit, 2, 4, 1.0\n";
+        let cursor = Cursor::new(data);
+
+        // when ...
+        let mut reader = TransactionReader::new(cursor);
+        let rows: Vec<Row> = reader.rows().collect();
+
+        // then ...
+        let batches: Vec<Option<&str>> = rows.iter().map(|row| row.batch.as_deref()).collect();
+        assert_eq!(batches, vec![Some("a"), Some("a"), None, None]);
+        let valid: Vec<bool> = rows.iter().map(|row| row.transaction.is_ok()).collect();
+        assert_eq!(valid, vec![true, false, true, true]);
+    }
+
+    #[test]
+    fn test_transaction_reader_gzip_input() {
+        // given ...
+        let data = "type, client, tx, amount\n\
+        deposit, 1, 1, 1.0\n";
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        io::Write::write_all(&mut encoder, data.as_bytes()).unwrap();
+        let cursor = Cursor::new(encoder.finish().unwrap());
+
+        // when ...
+        let mut reader = TransactionReader::new(cursor);
+        let transactions: Vec<Transaction> = reader.iter().collect();
+
+        // then ...
+        assert_eq!(
+            transactions,
+            vec![Transaction::Deposit(DepositTransaction {
+                client: ClientId(1),
+                tx: TxId(1),
+                amount: Decimal::new(10, 1),
+                timestamp: None,
+            })]
+        );
+    }
+
+    #[test]
+    fn test_transaction_reader_with_dialect() {
+        // given ...
+        let dialect = Dialect {
+            delimiter: ';',
+            has_headers: false,
+            order: ["client", "txn_id", "kind", "value"]
+                .map(String::from)
+                .to_vec(),
+            columns: [("txn_id", "tx"), ("kind", "type"), ("value", "amount")]
+                .map(|(from, to)| (String::from(from), String::from(to)))
+                .into(),
+            case_insensitive_types: true,
+            ..Dialect::default()
+        };
+        let data = "1; 1; DEPOSIT; 1.0\n\
+        2; 2; Withdrawal; 2.0\n";
+        let cursor = Cursor::new(data);
+
+        // when ...
+        let mut reader = TransactionReader::with_dialect(cursor, dialect);
+        let transactions: Vec<Transaction> = reader.iter().collect();
+
+        // then ...
+        assert_eq!(
+            transactions,
+            vec![
+                Transaction::Deposit(DepositTransaction {
+                    client: ClientId(1),
+                    tx: TxId(1),
+                    amount: Decimal::new(10, 1),
+                    timestamp: None,
+                }),
+                Transaction::Withdrawal(WithdrawalTransaction {
+                    client: ClientId(2),
+                    tx: TxId(2),
+                    amount: Decimal::new(20, 1),
+                    timestamp: None,
+                }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_references_to_unknown_ids_are_not_assigned() {
+        // given ...
+        let data = "type,client,tx,amount\n\
+        deposit,alice,DEP-1,1.0\n\
+        dispute,alice,DEP-9,\n\
+        dispute,bob,DEP-1,\n\
+        resolve,alice,DEP-1,\n\
+        close,bob,CLS-1,\n";
+        let cursor = Cursor::new(data);
+
+        // when ...
+        let mut reader = TransactionReader::new(cursor).with_ids(IdMap::default());
+        let (clients, txs): (Vec<ClientId>, Vec<TxId>) = reader
+            .iter()
+            .map(|transaction| (transaction.client(), transaction.tx()))
+            .unzip();
+        let ids = reader.into_ids().unwrap();
+
+        // then ...
+        assert_eq!(
+            clients,
+            vec![
+                ClientId(1),
+                ClientId(1),
+                IdMap::UNKNOWN_CLIENT,
+                ClientId(1),
+                IdMap::UNKNOWN_CLIENT
+            ]
+        );
+        assert_eq!(
+            txs,
+            vec![TxId(1), IdMap::UNKNOWN_TX, TxId(1), TxId(1), TxId(2)]
+        );
+        assert_eq!(ids.find_tx("DEP-9"), None);
+        assert_eq!(ids.find_client("bob"), None);
+    }
 }
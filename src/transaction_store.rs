@@ -1,9 +1,22 @@
+use crate::id::TxId;
 use crate::stored_transaction::StoredTransaction;
+use rust_decimal::Decimal;
 use std::collections::HashMap;
 
 #[derive(Debug, Default)]
 pub struct TransactionStore {
-    transactions: HashMap<u32, StoredTransaction>,
+    transactions: HashMap<TxId, StoredTransaction>,
+    /// Transactions overwritten by a later one reusing their tx id. Their effect on the
+    /// balances remains, so they still count towards the funds the ledger holds.
+    replaced: Vec<StoredTransaction>,
+    charged_back: Vec<Decimal>,
+}
+
+/// The lengths of the append-only parts of a [`TransactionStore`] at some point.
+#[derive(Debug, Clone, Copy)]
+pub struct Checkpoint {
+    replaced: usize,
+    charged_back: usize,
 }
 
 impl TransactionStore {
@@ -12,10 +25,47 @@ impl TransactionStore {
         T: Into<StoredTransaction>,
     {
         let stored: StoredTransaction = transaction.into();
-        self.transactions.insert(stored.tx(), stored);
+        if let Some(replaced) = self.transactions.insert(stored.tx(), stored) {
+            self.replaced.push(replaced);
+        }
     }
 
-    pub fn get(&self, tx: u32) -> Option<&StoredTransaction> {
+    pub fn get(&self, tx: TxId) -> Option<&StoredTransaction> {
         self.transactions.get(&tx)
     }
+
+    /// Puts back what [`TransactionStore::get`] returned for the tx id earlier.
+    pub fn restore(&mut self, tx: TxId, stored: Option<StoredTransaction>) {
+        match stored {
+            Some(stored) => self.transactions.insert(tx, stored),
+            None => self.transactions.remove(&tx),
+        };
+    }
+
+    pub fn record_chargeback(&mut self, amount: Decimal) {
+        self.charged_back.push(amount);
+    }
+
+    /// The amounts taken back by every chargeback so far.
+    pub fn chargebacks(&self) -> &[Decimal] {
+        &self.charged_back
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            replaced: self.replaced.len(),
+            charged_back: self.charged_back.len(),
+        }
+    }
+
+    /// Forgets the replacements and chargebacks recorded since the checkpoint.
+    pub fn truncate(&mut self, checkpoint: Checkpoint) {
+        self.replaced.truncate(checkpoint.replaced);
+        self.charged_back.truncate(checkpoint.charged_back);
+    }
+
+    /// Every stored transaction, including the replaced ones.
+    pub fn iter(&self) -> impl Iterator<Item = &StoredTransaction> {
+        self.transactions.values().chain(&self.replaced)
+    }
 }
@@ -1,6 +1,6 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, Deserialize, Serialize, PartialEq, Eq, Hash, PartialOrd, Ord, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
 pub enum TransactionType {
     Deposit,
@@ -8,4 +8,23 @@ pub enum TransactionType {
     Dispute,
     Resolve,
     Chargeback,
+    /// Opens an account, or activates a pending or frozen one, without moving money.
+    Open,
+    /// Closes an account, paying out its available balance.
+    Close,
+}
+
+impl TransactionType {
+    /// The lowercase name used in the CSV `type` column.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Open => "open",
+            TransactionType::Close => "close",
+        }
+    }
 }
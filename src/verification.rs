@@ -0,0 +1,75 @@
+use crate::id::ClientId;
+use rust_decimal::Decimal;
+use std::fmt;
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq)]
+pub enum Violation {
+    #[error(
+        "Account ({client}) total {total} does not equal available {available} plus held {held}"
+    )]
+    TotalMismatch {
+        client: ClientId,
+        available: Decimal,
+        held: Decimal,
+        total: Decimal,
+    },
+    #[error("Account ({client}) held {held} does not equal open disputes sum {disputed}")]
+    HeldMismatch {
+        client: ClientId,
+        held: Decimal,
+        disputed: Decimal,
+    },
+    #[error("Account ({client}) has negative available funds {available}")]
+    NegativeAvailable {
+        client: ClientId,
+        available: Decimal,
+    },
+    #[error("Account ({client}) is locked with {disputes} open dispute(s)")]
+    LockedWithOpenDisputes { client: ClientId, disputes: usize },
+    #[error(
+        "Sum of account balances {balances} does not equal deposits minus withdrawals minus chargebacks {expected}"
+    )]
+    BalanceMismatch {
+        balances: Decimal,
+        expected: Decimal,
+    },
+    #[error("Sum of account balances or stored transactions overflows")]
+    BalanceOverflow,
+}
+
+#[derive(Debug, Default, PartialEq, Error)]
+pub struct VerificationReport {
+    violations: Vec<Violation>,
+}
+
+impl VerificationReport {
+    pub fn push(&mut self, violation: Violation) {
+        self.violations.push(violation);
+    }
+
+    pub fn extend<I>(&mut self, violations: I)
+    where
+        I: IntoIterator<Item = Violation>,
+    {
+        self.violations.extend(violations);
+    }
+
+    pub fn is_clean(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+}
+
+impl fmt::Display for VerificationReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ledger invariant violation(s)", self.violations.len())?;
+        for violation in &self.violations {
+            write!(f, "\n  - {violation}")?;
+        }
+        Ok(())
+    }
+}
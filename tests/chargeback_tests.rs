@@ -21,13 +21,13 @@ fn test_clean_chargeback() {
     let mut output_reader = ChannelByteReader::new(rx);
 
     // when ...
-    LedgerSystem::new(Ledger::default(), input, output).run();
+    LedgerSystem::new(Ledger::default(), input, output).run().unwrap();
 
     // then ...
     assert_eq!(
         output_reader.read_to_string().unwrap(),
-        "client,available,held,total,locked\n\
-        1,0.0,0.0,0.0,true\n"
+        "client,available,held,total,locked,status,lock_reason\n\
+        1,0.0,0.0,0.0,true,locked,\n"
     );
     TEST_LOGS.with_borrow(|logs| {
         assert_eq!(*logs, Vec::<String>::new());
@@ -49,13 +49,13 @@ fn test_chargeback_on_locked_account() {
     let mut output_reader = ChannelByteReader::new(rx);
 
     // when ...
-    LedgerSystem::new(Ledger::default(), input, output).run();
+    LedgerSystem::new(Ledger::default(), input, output).run().unwrap();
 
     // then ...
     assert_eq!(
         output_reader.read_to_string().unwrap(),
-        "client,available,held,total,locked\n\
-        1,0.0,0.0,0.0,true\n"
+        "client,available,held,total,locked,status,lock_reason\n\
+        1,0.0,0.0,0.0,true,locked,\n"
     );
     TEST_LOGS.with_borrow(|logs| {
         assert_eq!(*logs, vec![String::from("Account (1) is locked")]);
@@ -75,13 +75,13 @@ fn test_chargeback_on_a_non_existent_dispute() {
     let mut output_reader = ChannelByteReader::new(rx);
 
     // when ...
-    LedgerSystem::new(Ledger::default(), input, output).run();
+    LedgerSystem::new(Ledger::default(), input, output).run().unwrap();
 
     // then ...
     assert_eq!(
         output_reader.read_to_string().unwrap(),
-        "client,available,held,total,locked\n\
-        1,100.0,0,100.0,false\n"
+        "client,available,held,total,locked,status,lock_reason\n\
+        1,100.0,0,100.0,false,active,\n"
     );
     TEST_LOGS.with_borrow(|logs| {
         assert_eq!(
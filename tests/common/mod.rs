@@ -18,7 +18,7 @@ impl Write for ChannelByteWriter {
         let copy = buf.to_vec();
         self.sender
             .send(Some(copy))
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+            .map_err(io::Error::other)?;
         Ok(buf.len())
     }
 
@@ -52,7 +52,7 @@ impl ChannelByteReader {
 }
 
 thread_local! {
-    pub static TEST_LOGS: RefCell<Vec<String>> = RefCell::new(Vec::new());
+    pub static TEST_LOGS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
 }
 static TEST_LOGS_INIT: Once = Once::new();
 
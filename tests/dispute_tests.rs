@@ -19,13 +19,13 @@ fn test_clean_dispute() {
     let mut output_reader = ChannelByteReader::new(rx);
 
     // when ...
-    LedgerSystem::new(Ledger::default(), input, output).run();
+    LedgerSystem::new(Ledger::default(), input, output).run().unwrap();
 
     // then ...
     assert_eq!(
         output_reader.read_to_string().unwrap(),
-        "client,available,held,total,locked\n\
-        1,0.0,100.0,100.0,false\n"
+        "client,available,held,total,locked,status,lock_reason\n\
+        1,0.0,100.0,100.0,false,active,\n"
     );
     TEST_LOGS.with_borrow(|logs| {
         assert_eq!(*logs, Vec::<String>::new());
@@ -48,13 +48,13 @@ fn test_dispute_on_locked_account() {
     let mut output_reader = ChannelByteReader::new(rx);
 
     // when ...
-    LedgerSystem::new(Ledger::default(), input, output).run();
+    LedgerSystem::new(Ledger::default(), input, output).run().unwrap();
 
     // then ...
     assert_eq!(
         output_reader.read_to_string().unwrap(),
-        "client,available,held,total,locked\n\
-        1,100.0,0.0,100.0,true\n"
+        "client,available,held,total,locked,status,lock_reason\n\
+        1,100.0,0.0,100.0,true,locked,\n"
     );
     TEST_LOGS.with_borrow(|logs| {
         assert_eq!(*logs, vec![String::from("Account (1) is locked")]);
@@ -75,13 +75,13 @@ fn test_dispute_on_dispute_already_in_progress() {
     let mut output_reader = ChannelByteReader::new(rx);
 
     // when ...
-    LedgerSystem::new(Ledger::default(), input, output).run();
+    LedgerSystem::new(Ledger::default(), input, output).run().unwrap();
 
     // then ...
     assert_eq!(
         output_reader.read_to_string().unwrap(),
-        "client,available,held,total,locked\n\
-        1,0.0,100.0,100.0,false\n"
+        "client,available,held,total,locked,status,lock_reason\n\
+        1,0.0,100.0,100.0,false,active,\n"
     );
     TEST_LOGS.with_borrow(|logs| {
         assert_eq!(
@@ -107,13 +107,13 @@ fn test_dispute_on_withdrawal() {
     let mut output_reader = ChannelByteReader::new(rx);
 
     // when ...
-    LedgerSystem::new(Ledger::default(), input, output).run();
+    LedgerSystem::new(Ledger::default(), input, output).run().unwrap();
 
     // then ...
     assert_eq!(
         output_reader.read_to_string().unwrap(),
-        "client,available,held,total,locked\n\
-        1,50.0,0,50.0,false\n"
+        "client,available,held,total,locked,status,lock_reason\n\
+        1,50.0,0,50.0,false,active,\n"
     );
     TEST_LOGS.with_borrow(|logs| {
         assert_eq!(
@@ -138,13 +138,13 @@ fn test_dispute_on_nonexistent_transaction() {
     let mut output_reader = ChannelByteReader::new(rx);
 
     // when ...
-    LedgerSystem::new(Ledger::default(), input, output).run();
+    LedgerSystem::new(Ledger::default(), input, output).run().unwrap();
 
     // then ...
     assert_eq!(
         output_reader.read_to_string().unwrap(),
-        "client,available,held,total,locked\n\
-        1,100.0,0,100.0,false\n"
+        "client,available,held,total,locked,status,lock_reason\n\
+        1,100.0,0,100.0,false,active,\n"
     );
     TEST_LOGS.with_borrow(|logs| {
         assert_eq!(
@@ -168,13 +168,13 @@ fn test_dispute_on_non_owned_transaction() {
     let mut output_reader = ChannelByteReader::new(rx);
 
     // when ...
-    LedgerSystem::new(Ledger::default(), input, output).run();
+    LedgerSystem::new(Ledger::default(), input, output).run().unwrap();
 
     // then ...
     let result = output_reader.read_to_string().unwrap();
-    assert!(result.starts_with("client,available,held,total,locked\n"));
-    assert!(result.contains("1,100.0,0,100.0,false\n"));
-    assert!(result.contains("2,50.0,0,50.0,false\n"));
+    assert!(result.starts_with("client,available,held,total,locked,status,lock_reason\n"));
+    assert!(result.contains("1,100.0,0,100.0,false,active,\n"));
+    assert!(result.contains("2,50.0,0,50.0,false,active,\n"));
     TEST_LOGS.with_borrow(|logs| {
         assert_eq!(
             *logs,
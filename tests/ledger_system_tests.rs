@@ -0,0 +1,824 @@
+mod common;
+
+use crate::common::{ChannelByteReader, ChannelByteWriter, TEST_LOGS, TestLogger};
+use glowing_fiesta::blocklist::Blocklist;
+use glowing_fiesta::error::{Category, Coded, ErrorCode};
+use glowing_fiesta::event::JsonLinesObserver;
+use glowing_fiesta::id::{ClientId, IdMap, TxId};
+use glowing_fiesta::ledger::Ledger;
+use glowing_fiesta::ledger_system::{LedgerSystem, OutputFormat, RunSummary};
+use glowing_fiesta::metrics::MetricsFormat;
+use glowing_fiesta::payout::PayoutObserver;
+use glowing_fiesta::{account_state, ledger, ledger_system};
+use chrono::TimeDelta;
+use std::io;
+use std::io::{BufWriter, Cursor};
+use std::sync::mpsc;
+
+#[test]
+fn test_run_summary_counts_rejections() {
+    // given ...
+    TestLogger::reset();
+    let data = "type,client,tx,amount\n\
+        deposit,1,1,100.0\n\
+        deposit,1,2,\n\
+        withdrawal,1,3,200.0\n\
+        dispute,1,9,\n";
+    let input = Cursor::new(data);
+
+    // when ...
+    let summary = LedgerSystem::new(Ledger::default(), input, Vec::new())
+        .run()
+        .unwrap();
+
+    // then ...
+    assert_eq!(
+        summary,
+        RunSummary {
+            rejected_rows: 1,
+            rejected_transactions: 2,
+            rejected_batches: 0,
+        }
+    );
+    assert!(!summary.is_clean());
+}
+
+#[test]
+fn test_error_handler_receives_coded_errors() {
+    // given ...
+    TestLogger::reset();
+    let data = "type,client,tx,amount\n\
+        deposit,1,1,100.0\n\
+        deposit,1,2,\n\
+        deposit,x,3,1.0\n\
+        withdrawal,1,4,200.0\n\
+        dispute,1,9,\n";
+    let input = Cursor::new(data);
+    let (tx, rx) = mpsc::channel();
+
+    // when ...
+    LedgerSystem::new(Ledger::default(), input, Vec::new())
+        .with_error_handler(move |e| {
+            let row = e.location().map(|location| location.row);
+            tx.send((e.code(), e.category(), row, e.client(), e.tx()))
+                .unwrap();
+        })
+        .run()
+        .unwrap();
+
+    // then ...
+    let errors: Vec<_> = rx.try_iter().collect();
+    assert_eq!(
+        errors,
+        vec![
+            (
+                ErrorCode::MissingAmount,
+                Category::Validation,
+                Some(2),
+                Some(ClientId(1)),
+                Some(TxId(2))
+            ),
+            (
+                ErrorCode::InvalidField,
+                Category::Parse,
+                Some(3),
+                None,
+                None
+            ),
+            (
+                ErrorCode::InsufficientFunds,
+                Category::Business,
+                Some(4),
+                Some(ClientId(1)),
+                None
+            ),
+            (
+                ErrorCode::DisputeTransactionNotFound,
+                Category::Validation,
+                Some(5),
+                Some(ClientId(1)),
+                Some(TxId(9))
+            ),
+        ]
+    );
+    assert_eq!(errors[0].0.as_str(), "E_MISSING_AMOUNT");
+}
+
+#[test]
+fn test_metrics_are_written_after_the_run() {
+    // given ...
+    TestLogger::reset();
+    let data = "type,client,tx,amount,batch\n\
+        deposit,1,1,100.0,\n\
+        dispute,1,1,,\n\
+        deposit,2,2,,\n\
+        deposit,3,3,10.0,a\n\
+        withdrawal,3,4,50.0,a\n";
+    let (tx, rx) = mpsc::channel();
+    let mut metrics_reader = ChannelByteReader::new(rx);
+
+    // when ...
+    LedgerSystem::new(Ledger::default(), Cursor::new(data), Vec::new())
+        .with_metrics(ChannelByteWriter::new(tx), MetricsFormat::Json)
+        .run()
+        .unwrap();
+
+    // then ...
+    let metrics: serde_json::Value =
+        serde_json::from_str(&metrics_reader.read_to_string().unwrap()).unwrap();
+    assert_eq!(metrics["rows_read"], 5);
+    assert_eq!(
+        metrics["rows_rejected"],
+        serde_json::json!({"E_MISSING_AMOUNT": 1, "E_INSUFFICIENT_FUNDS": 2})
+    );
+    assert_eq!(
+        metrics["transactions_applied"],
+        serde_json::json!({"deposit": 1, "dispute": 1})
+    );
+    assert_eq!(metrics["accounts_created"], 1);
+    assert_eq!(metrics["open_disputes"], 1);
+    assert_eq!(metrics["funds_held"], "100.0");
+    assert_eq!(metrics["processing_seconds"]["count"], 4);
+}
+
+#[test]
+fn test_journal_replays_to_the_same_state() {
+    // given ...
+    TestLogger::reset();
+    let data = "type,client,tx,amount\n\
+        deposit,1,1,100.0\n\
+        withdrawal,1,2,500.0\n\
+        deposit,2,3,50.0\n\
+        dispute,2,3,\n";
+    let (journal_tx, journal_rx) = mpsc::channel();
+    let mut journal_reader = ChannelByteReader::new(journal_rx);
+    let (tx, rx) = mpsc::channel();
+    let mut output_reader = ChannelByteReader::new(rx);
+    LedgerSystem::new(Ledger::default(), Cursor::new(data), Vec::new())
+        .with_journal(ChannelByteWriter::new(journal_tx))
+        .run()
+        .unwrap();
+    let journal = journal_reader.read_to_string().unwrap();
+
+    // when ...
+    let summary = LedgerSystem::new(
+        Ledger::default(),
+        Cursor::new(journal.clone()),
+        ChannelByteWriter::new(tx),
+    )
+    .with_format(OutputFormat::Json)
+    .run()
+    .unwrap();
+
+    // then ...
+    assert_eq!(
+        journal,
+        "type,client,tx,amount,timestamp\n\
+        deposit,1,1,100.0,\n\
+        deposit,2,3,50.0,\n\
+        dispute,2,3,,\n"
+    );
+    assert!(summary.is_clean());
+    let output = output_reader.read_to_string().unwrap();
+    assert!(output.contains("\"client\": 2,\n    \"available\": \"0.0\",\n    \"held\": \"50.0\""));
+}
+
+#[test]
+fn test_metrics_report_overflowing_funds_held() {
+    // given ...
+    TestLogger::reset();
+    let data = "type,client,tx,amount\n\
+        deposit,1,1,79228162514264337593543950335\n\
+        deposit,2,2,79228162514264337593543950335\n\
+        dispute,1,1,\n\
+        dispute,2,2,\n";
+    let (tx, rx) = mpsc::channel();
+    let mut metrics_reader = ChannelByteReader::new(rx);
+
+    // when ...
+    LedgerSystem::new(Ledger::default(), Cursor::new(data), Vec::new())
+        .with_metrics(ChannelByteWriter::new(tx), MetricsFormat::Prometheus)
+        .run()
+        .unwrap();
+
+    // then ...
+    let metrics = metrics_reader.read_to_string().unwrap();
+    assert!(metrics.contains("\nledger_funds_held NaN\n"), "{metrics}");
+    TEST_LOGS.with_borrow(|logs| {
+        assert_eq!(
+            *logs,
+            vec![String::from(
+                "Funds held across all accounts overflow; the gauge is reported as NaN"
+            )]
+        );
+    });
+}
+
+#[test]
+fn test_strict_run_stops_at_rejected_transaction() {
+    // given ...
+    TestLogger::reset();
+    let data = "type,client,tx,amount\n\
+        deposit,1,1,100.0\n\
+        withdrawal,1,2,200.0\n\
+        deposit,1,3,100.0\n";
+    let input = Cursor::new(data);
+    let mut output = Vec::new();
+    let (tx, rx) = mpsc::channel();
+    let mut metrics_reader = ChannelByteReader::new(rx);
+
+    // when ...
+    let result = LedgerSystem::new(Ledger::default(), input, &mut output)
+        .with_strict(true)
+        .with_metrics(ChannelByteWriter::new(tx), MetricsFormat::Json)
+        .run();
+
+    // then ...
+    let error = result.unwrap_err();
+    assert!(matches!(
+        error,
+        ledger_system::Error::RejectedTransaction {
+            location: ledger_system::Location { row: 2, .. },
+            source: ledger::Error::AccountStateError(account_state::Error::InsufficientFunds {
+                client: ClientId(1)
+            }),
+        }
+    ));
+    assert_eq!(
+        error.to_string(),
+        "Row 2 was rejected: Account (1) has insufficient funds"
+    );
+    assert!(output.is_empty());
+    assert_eq!(metrics_reader.read_to_string().unwrap(), "");
+}
+
+#[test]
+fn test_verify_each_stops_at_the_violating_row() {
+    // given ...
+    TestLogger::reset();
+    let data = "type,client,tx,amount\n\
+        deposit,1,1,100.0\n\
+        dispute,1,1,\n\
+        withdrawal,1,2,100.0\n\
+        deposit,1,3,100.0\n";
+    let input = Cursor::new(data);
+    let mut output = Vec::new();
+    let (tx, rx) = mpsc::channel();
+    let ledger = Ledger::default().with_verify_each(true);
+
+    // when ...
+    let result = LedgerSystem::new(ledger, input, &mut output)
+        .with_transaction_handler(move |transaction, _| tx.send(transaction.tx()).unwrap())
+        .run();
+
+    // then ...
+    let error = result.unwrap_err();
+    assert!(matches!(
+        error,
+        ledger_system::Error::Verification {
+            location: ledger_system::Location { row: 3, .. },
+            ..
+        }
+    ));
+    assert!(error.is_fatal());
+    assert_eq!(error.code(), ErrorCode::InvariantViolation);
+    assert_eq!(error.category(), Category::Internal);
+    assert_eq!(
+        error.to_string(),
+        "Ledger verification failed after row 3: 1 ledger invariant violation(s)\n  \
+        - Account (1) has negative available funds -100.0"
+    );
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), [TxId(1), TxId(1), TxId(2)]);
+    assert!(output.is_empty());
+}
+
+#[test]
+fn test_strict_run_stops_at_malformed_row() {
+    // given ...
+    TestLogger::reset();
+    let data = "type,client,tx,amount\n\
+        deposit,1,1,100.0\n\
+        deposit,1,2,\n";
+    let input = Cursor::new(data);
+    let mut output = Vec::new();
+
+    // when ...
+    let result = LedgerSystem::new(Ledger::default(), input, &mut output)
+        .with_strict(true)
+        .run();
+
+    // then ...
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Row 2 is malformed: Deposit transaction must have an amount"
+    );
+    assert!(output.is_empty());
+}
+
+#[test]
+fn test_batch_is_applied_atomically() {
+    // given ...
+    TestLogger::reset();
+    let data = "type,client,tx,amount,batch\n\
+        deposit,1,1,100.0,\n\
+        deposit,1,2,50.0,b1\n\
+        withdrawal,1,3,1.0,b1\n\
+        deposit,2,4,20.0,b2\n\
+        withdrawal,2,5,30.0,b2\n";
+    let input = Cursor::new(data);
+    let (tx, rx) = mpsc::channel();
+    let output = ChannelByteWriter::new(tx);
+    let mut output_reader = ChannelByteReader::new(rx);
+
+    // when ...
+    let summary = LedgerSystem::new(Ledger::default(), input, output)
+        .run()
+        .unwrap();
+
+    // then ...
+    assert_eq!(
+        output_reader.read_to_string().unwrap(),
+        "client,available,held,total,locked,status,lock_reason\n\
+        1,149.0,0,149.0,false,active,\n"
+    );
+    assert_eq!(
+        summary,
+        RunSummary {
+            rejected_rows: 0,
+            rejected_transactions: 2,
+            rejected_batches: 1,
+        }
+    );
+    TEST_LOGS.with_borrow(|logs| {
+        assert_eq!(
+            *logs,
+            vec![String::from(
+                "Batch b2 was rejected at row 5: Account (2) has insufficient funds"
+            )]
+        );
+    });
+}
+
+#[test]
+fn test_transaction_handler_sees_every_outcome_in_order() {
+    // given ...
+    TestLogger::reset();
+    let data = "type,client,tx,amount,batch\n\
+        deposit,1,1,100.0,\n\
+        deposit,2,2,20.0,b1\n\
+        withdrawal,2,3,30.0,b1\n\
+        withdrawal,1,4,10.0,\n";
+    let input = Cursor::new(data);
+    let (tx, rx) = mpsc::channel();
+
+    // when ...
+    LedgerSystem::new(Ledger::default(), input, Vec::new())
+        .with_transaction_handler(move |transaction, error| {
+            tx.send((transaction.tx(), error.map(Coded::code))).unwrap();
+        })
+        .run()
+        .unwrap();
+
+    // then ...
+    let outcomes: Vec<_> = rx.try_iter().collect();
+    assert_eq!(
+        outcomes,
+        vec![
+            (TxId(1), None),
+            (TxId(2), Some(ErrorCode::InsufficientFunds)),
+            (TxId(3), Some(ErrorCode::InsufficientFunds)),
+            (TxId(4), None),
+        ]
+    );
+}
+
+#[test]
+fn test_batch_with_malformed_member_is_rejected() {
+    // given ...
+    TestLogger::reset();
+    let data = "type,client,tx,amount,batch\n\
+        deposit,1,1,100.0,b1\n\
+        withdrawal,1,2,,b1\n";
+    let input = Cursor::new(data);
+    let (tx, rx) = mpsc::channel();
+    let output = ChannelByteWriter::new(tx);
+    let mut output_reader = ChannelByteReader::new(rx);
+
+    // when ...
+    let summary = LedgerSystem::new(Ledger::default(), input, output)
+        .run()
+        .unwrap();
+
+    // then ...
+    assert_eq!(
+        output_reader.read_to_string().unwrap(),
+        "client,available,held,total,locked,status,lock_reason\n"
+    );
+    assert_eq!(
+        summary,
+        RunSummary {
+            rejected_rows: 1,
+            rejected_transactions: 1,
+            rejected_batches: 1,
+        }
+    );
+}
+
+#[test]
+fn test_inputs_share_one_ledger() {
+    // given ...
+    TestLogger::reset();
+    let first = "type,client,tx,amount\n\
+        deposit,1,1,100.0\n";
+    let second = "type,client,tx,amount\n\
+        dispute,1,1,\n\
+        withdrawal,1,2,500.0\n";
+    let inputs = vec![
+        (String::from("first.csv"), Cursor::new(first)),
+        (String::from("second.csv"), Cursor::new(second)),
+    ];
+    let (tx, rx) = mpsc::channel();
+    let output = ChannelByteWriter::new(tx);
+    let mut output_reader = ChannelByteReader::new(rx);
+
+    // when ...
+    LedgerSystem::from_inputs(Ledger::default(), inputs, output)
+        .run()
+        .unwrap();
+
+    // then ...
+    assert_eq!(
+        output_reader.read_to_string().unwrap(),
+        "client,available,held,total,locked,status,lock_reason\n\
+        1,0.0,100.0,100.0,false,active,\n"
+    );
+    TEST_LOGS.with_borrow(|logs| {
+        assert_eq!(
+            *logs,
+            vec![String::from(
+                "second.csv:3: Account (1) has insufficient funds"
+            )]
+        );
+    });
+}
+
+#[test]
+fn test_strict_error_names_the_input() {
+    // given ...
+    TestLogger::reset();
+    let inputs = vec![(
+        String::from("partner.csv"),
+        Cursor::new("type,client,tx,amount\ndeposit,1,1,\n"),
+    )];
+
+    // when ...
+    let result = LedgerSystem::from_inputs(Ledger::default(), inputs, Vec::new())
+        .with_strict(true)
+        .run();
+
+    // then ...
+    assert_eq!(
+        result.unwrap_err().to_string(),
+        "Row 1 (partner.csv:2) is malformed: Deposit transaction must have an amount"
+    );
+}
+
+#[test]
+fn test_external_ids_are_mapped_and_saved() {
+    // given ...
+    TestLogger::reset();
+    let ids = IdMap::load("kind,external,internal\nclient,alice,7\n".as_bytes()).unwrap();
+    let data = "type,client,tx,amount\n\
+        deposit,alice,DEP-1,100.0\n\
+        deposit,bob,DEP-2,50.0\n\
+        dispute,alice,DEP-1,\n";
+    let input = Cursor::new(data);
+    let (tx, rx) = mpsc::channel();
+    let output = ChannelByteWriter::new(tx);
+    let mut output_reader = ChannelByteReader::new(rx);
+    let (ids_tx, ids_rx) = mpsc::channel();
+    let mut ids_reader = ChannelByteReader::new(ids_rx);
+
+    // when ...
+    let summary = LedgerSystem::new(Ledger::default(), input, output)
+        .with_ids(ids, ChannelByteWriter::new(ids_tx))
+        .run()
+        .unwrap();
+
+    // then ...
+    assert!(summary.is_clean());
+    let mut accounts: Vec<_> = output_reader
+        .read_to_string()
+        .unwrap()
+        .lines()
+        .map(String::from)
+        .collect();
+    accounts.sort();
+    assert_eq!(
+        accounts,
+        vec![
+            "7,0.0,100.0,100.0,false,active,",
+            "8,50.0,0,50.0,false,active,",
+            "client,available,held,total,locked,status,lock_reason",
+        ]
+    );
+    assert_eq!(
+        ids_reader.read_to_string().unwrap(),
+        "kind,external,internal\n\
+        client,alice,7\n\
+        client,bob,8\n\
+        tx,DEP-1,1\n\
+        tx,DEP-2,2\n"
+    );
+}
+
+#[test]
+fn test_blocklist_lists_external_ids() {
+    // given ...
+    TestLogger::reset();
+    let path = std::env::temp_dir().join(format!("partner-blocklist-{}.txt", std::process::id()));
+    std::fs::write(&path, "bob # sanctioned\n").unwrap();
+    let blocklist = Blocklist::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let data = "type,client,tx,amount\n\
+        deposit,alice,DEP-1,100.0\n\
+        deposit,bob,DEP-2,50.0\n";
+    let input = Cursor::new(data);
+    let (tx, rx) = mpsc::channel();
+    let output = ChannelByteWriter::new(tx);
+    let mut output_reader = ChannelByteReader::new(rx);
+
+    // when ...
+    let summary = LedgerSystem::new(Ledger::default().with_blocklist(blocklist), input, output)
+        .with_ids(IdMap::default(), Vec::new())
+        .run()
+        .unwrap();
+
+    // then ...
+    assert_eq!(summary.rejected_transactions, 1);
+    assert_eq!(
+        output_reader.read_to_string().unwrap(),
+        "client,available,held,total,locked,status,lock_reason\n\
+        1,100.0,0,100.0,false,active,\n"
+    );
+    TEST_LOGS.with_borrow(|logs| {
+        assert_eq!(
+            *logs,
+            vec![String::from(
+                "Account (2) is blocklisted, transaction 2 rejected: sanctioned"
+            )]
+        );
+    });
+}
+
+#[test]
+fn test_out_of_order_timestamps_are_rejected() {
+    // given ...
+    TestLogger::reset();
+    let data = "type,client,tx,amount,timestamp\n\
+        deposit,1,1,100.0,2024-03-01T12:00:00Z\n\
+        deposit,1,2,10.0,2024-03-01T11:59:30Z\n\
+        deposit,1,3,20.0,2024-03-01T13:00:00+02:00\n\
+        deposit,2,4,5.0,2024-02-01T00:00:00Z\n";
+    let (journal_tx, journal_rx) = mpsc::channel();
+    let mut journal_reader = ChannelByteReader::new(journal_rx);
+    let ledger = Ledger::default().with_timestamp_tolerance(TimeDelta::seconds(10));
+
+    // when ...
+    let summary = LedgerSystem::from_inputs(
+        ledger,
+        vec![(String::from("march.csv"), Cursor::new(data))],
+        Vec::new(),
+    )
+    .with_journal(ChannelByteWriter::new(journal_tx))
+    .run()
+    .unwrap();
+
+    // then ...
+    assert_eq!(summary.rejected_transactions, 2);
+    assert_eq!(
+        journal_reader.read_to_string().unwrap(),
+        "type,client,tx,amount,timestamp\n\
+        deposit,1,1,100.0,2024-03-01T12:00:00Z\n\
+        deposit,2,4,5.0,2024-02-01T00:00:00Z\n"
+    );
+    TEST_LOGS.with_borrow(|logs| {
+        assert_eq!(
+            *logs,
+            vec![
+                String::from(
+                    "march.csv:3: 2024-03-01T11:59:30Z: Account (1) transaction 2 at \
+                    2024-03-01T11:59:30Z is earlier than a previous transaction at \
+                    2024-03-01T12:00:00Z"
+                ),
+                String::from(
+                    "march.csv:4: 2024-03-01T11:00:00Z: Account (1) transaction 3 at \
+                    2024-03-01T11:00:00Z is earlier than a previous transaction at \
+                    2024-03-01T12:00:00Z"
+                ),
+            ]
+        );
+    });
+}
+
+const COMPRESSED: &str = "type,client,tx,amount\n\
+    deposit,1,1,100.0\n\
+    deposit,1,2,50.0\n\
+    withdrawal,1,3,25.0\n";
+
+fn gzip(data: &str) -> Vec<u8> {
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    std::io::Write::write_all(&mut encoder, data.as_bytes()).unwrap();
+    encoder.finish().unwrap()
+}
+
+fn zstd(data: &str) -> Vec<u8> {
+    zstd::encode_all(data.as_bytes(), 0).unwrap()
+}
+
+/// Runs a single input non-strictly, returning the error and whatever was written.
+fn run_unreadable(input: Vec<u8>) -> (ledger_system::Error, String) {
+    let inputs = vec![(String::from("partner.csv.gz"), Cursor::new(input))];
+    let (tx, rx) = mpsc::channel();
+    let mut output_reader = ChannelByteReader::new(rx);
+    let error = LedgerSystem::from_inputs(Ledger::default(), inputs, ChannelByteWriter::new(tx))
+        .run()
+        .unwrap_err();
+    (error, output_reader.read_to_string().unwrap())
+}
+
+#[test]
+fn test_truncated_gzip_input_is_fatal() {
+    // given ...
+    TestLogger::reset();
+    let mut input = gzip(COMPRESSED);
+    input.truncate(input.len() - 12);
+
+    // when ...
+    let (error, output) = run_unreadable(input);
+
+    // then ...
+    assert!(matches!(error, ledger_system::Error::Input { .. }));
+    assert!(error.is_fatal());
+    assert_eq!(error.code(), ErrorCode::Io);
+    assert!(error.to_string().starts_with("Failed to read partner.csv.gz: "));
+    assert_eq!(output, "");
+    TEST_LOGS.with_borrow(|logs| assert!(logs.is_empty()));
+}
+
+#[test]
+fn test_truncated_zstd_input_is_fatal() {
+    // given ...
+    TestLogger::reset();
+    let mut input = zstd(COMPRESSED);
+    input.truncate(input.len() - 4);
+
+    // when ...
+    let (error, output) = run_unreadable(input);
+
+    // then ...
+    assert!(matches!(error, ledger_system::Error::Input { .. }));
+    assert_eq!(error.code(), ErrorCode::Io);
+    assert_eq!(output, "");
+}
+
+#[test]
+fn test_gzip_input_with_garbage_body_is_fatal() {
+    // given ...
+    TestLogger::reset();
+    let mut input = gzip(COMPRESSED)[..10].to_vec();
+    input.extend_from_slice(b"this is not a deflate stream");
+
+    // when ...
+    let (error, output) = run_unreadable(input);
+
+    // then ...
+    assert!(matches!(error, ledger_system::Error::Input { .. }));
+    assert_eq!(error.code(), ErrorCode::Io);
+    assert_eq!(output, "");
+}
+
+/// A disk that is always full.
+struct Full;
+
+impl io::Write for Full {
+    fn write(&mut self, _: &[u8]) -> io::Result<usize> {
+        Err(io::ErrorKind::StorageFull.into())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Err(io::ErrorKind::StorageFull.into())
+    }
+}
+
+#[test]
+fn test_unwritten_events_fail_the_run() {
+    // given ...
+    let data = "type,client,tx,amount\n\
+        deposit,1,1,100.0\n";
+    let ledger = Ledger::default().with_observer(JsonLinesObserver::new(BufWriter::new(Full)));
+
+    // when ...
+    let result = LedgerSystem::new(ledger, Cursor::new(data), Vec::new()).run();
+
+    // then ...
+    let error = result.unwrap_err();
+    assert!(error.is_fatal());
+    assert_eq!(
+        error.to_string(),
+        "Failed to write output: Failed to write events: no storage space"
+    );
+}
+
+/// A disk that is full for the first write only.
+#[derive(Default)]
+struct FullOnce(bool);
+
+impl io::Write for FullOnce {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if std::mem::replace(&mut self.0, true) {
+            Ok(buf.len())
+        } else {
+            Err(io::ErrorKind::StorageFull.into())
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[test]
+fn test_payouts_have_a_header_without_payouts() {
+    // given ...
+    let data = "type,client,tx,amount\n\
+        deposit,1,1,100.0\n";
+    let (tx, rx) = mpsc::channel();
+    let mut payouts_reader = ChannelByteReader::new(rx);
+    let ledger =
+        Ledger::default().with_observer(PayoutObserver::new(ChannelByteWriter::new(tx)));
+
+    // when ...
+    LedgerSystem::new(ledger, Cursor::new(data), Vec::new())
+        .run()
+        .unwrap();
+
+    // then ...
+    assert_eq!(payouts_reader.read_to_string().unwrap(), "client,tx,amount\n");
+}
+
+#[test]
+fn test_unwritten_payouts_fail_the_run() {
+    // given ...
+    let data = "type,client,tx,amount\n\
+        open,1,1,\n\
+        deposit,1,2,100.0\n\
+        close,1,3,\n";
+    let ledger = Ledger::default().with_observer(PayoutObserver::new(FullOnce::default()));
+
+    // when ...
+    let result = LedgerSystem::new(ledger, Cursor::new(data), Vec::new()).run();
+
+    // then ...
+    let error = result.unwrap_err();
+    assert!(error.is_fatal());
+    assert_eq!(
+        error.to_string(),
+        "Failed to write output: Failed to write events: no storage space"
+    );
+}
+
+#[test]
+fn test_unwritten_metrics_fail_the_run() {
+    // given ...
+    let data = "type,client,tx,amount\n\
+        deposit,1,1,100.0\n";
+
+    // when ...
+    let result = LedgerSystem::new(Ledger::default(), Cursor::new(data), Vec::new())
+        .with_metrics(BufWriter::new(Full), MetricsFormat::Prometheus)
+        .run();
+
+    // then ...
+    let error = result.unwrap_err();
+    assert!(error.is_fatal());
+    assert_eq!(error.to_string(), "Failed to write output: no storage space");
+}
+
+#[test]
+fn test_run_on_another_thread() {
+    // given ...
+    let data = "type,client,tx,amount\n\
+        deposit,1,1,100.0\n";
+    let (tx, rx) = mpsc::channel();
+    let system = LedgerSystem::new(Ledger::default(), Cursor::new(data), Vec::new())
+        .with_journal(Vec::new())
+        .with_transaction_handler(move |transaction, _| tx.send(transaction.tx()).unwrap());
+
+    // when ...
+    let summary = std::thread::spawn(move || system.run()).join().unwrap();
+
+    // then ...
+    assert_eq!(summary.unwrap(), RunSummary::default());
+    assert_eq!(rx.try_iter().collect::<Vec<_>>(), vec![TxId(1)]);
+}
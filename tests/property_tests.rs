@@ -0,0 +1,292 @@
+use glowing_fiesta::account_state::AccountStatus;
+use glowing_fiesta::id::{ClientId, TxId};
+use glowing_fiesta::ledger::Ledger;
+use glowing_fiesta::transaction::*;
+use glowing_fiesta::transaction_type::TransactionType;
+use glowing_fiesta::verification::Violation;
+use proptest::prelude::*;
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+// Small id spaces so generated sequences collide on clients and tx ids the
+// way a messy partner file does: disputes of foreign transactions, resolves
+// without disputes, reused deposit ids and so on. Opens and closes take the row
+// number past the shared range as their tx id.
+const MAX_CLIENT: u64 = 4;
+const MAX_TX: u64 = 24;
+
+fn amount() -> impl Strategy<Value = Decimal> {
+    (0i64..=50_000).prop_map(|cents| Decimal::new(cents, 2))
+}
+
+fn transaction() -> impl Strategy<Value = (TransactionType, ClientId, TxId, Decimal)> {
+    let types = prop_oneof![
+        4 => Just(TransactionType::Deposit),
+        3 => Just(TransactionType::Withdrawal),
+        2 => Just(TransactionType::Dispute),
+        1 => Just(TransactionType::Resolve),
+        1 => Just(TransactionType::Chargeback),
+        1 => Just(TransactionType::Open),
+        1 => Just(TransactionType::Close),
+    ];
+    (types, 1..=MAX_CLIENT, 1..=MAX_TX, amount())
+        .prop_map(|(r#type, client, tx, amount)| (r#type, ClientId(client), TxId(tx), amount))
+}
+
+fn transactions() -> impl Strategy<Value = Vec<Transaction>> {
+    prop::collection::vec(transaction(), 0..64).prop_map(|rows| {
+        (1..)
+            .zip(rows)
+            .map(|(row, (r#type, client, tx, amount))| {
+                let unique = TxId(MAX_TX + row);
+                let timestamp = None;
+                match r#type {
+                    TransactionType::Deposit => Transaction::Deposit(DepositTransaction {
+                        client,
+                        tx,
+                        amount,
+                        timestamp,
+                    }),
+                    TransactionType::Withdrawal => Transaction::Withdrawal(WithdrawalTransaction {
+                        client,
+                        tx,
+                        amount,
+                        timestamp,
+                    }),
+                    TransactionType::Dispute => Transaction::Dispute(DisputeTransaction {
+                        client,
+                        tx,
+                        timestamp,
+                    }),
+                    TransactionType::Resolve => Transaction::Resolve(ResolveTransaction {
+                        client,
+                        tx,
+                        timestamp,
+                    }),
+                    TransactionType::Chargeback => Transaction::Chargeback(ChargebackTransaction {
+                        client,
+                        tx,
+                        timestamp,
+                    }),
+                    TransactionType::Open => Transaction::Open(OpenTransaction {
+                        client,
+                        tx: unique,
+                        timestamp,
+                    }),
+                    TransactionType::Close => Transaction::Close(CloseTransaction {
+                        client,
+                        tx: unique,
+                        timestamp,
+                    }),
+                }
+            })
+            .collect()
+    })
+}
+
+#[derive(Debug, Default, PartialEq, Clone)]
+struct ModelAccount {
+    available: Decimal,
+    held: Decimal,
+    total: Decimal,
+    locked: bool,
+    closed: bool,
+}
+
+/// A deliberately naive re-statement of the specification, kept independent of
+/// the crate's own types and code so the two can be cross-checked:
+///
+/// - a deposit credits available funds; a withdrawal debits them and fails when
+///   the total funds do not cover it; a deposit or withdrawal reusing a tx id
+///   replaces the earlier one for later disputes;
+/// - `open` and `close` may not reuse the tx id of an applied transaction;
+/// - a dispute moves a deposit's amount of the same client from available to
+///   held, a resolve moves it back and a chargeback removes it and locks the
+///   account; withdrawals cannot be disputed;
+/// - a locked or closed account refuses everything;
+/// - only an applied transaction leaves a new account behind; `open` opens one
+///   and `close` pays out the available funds once nothing is held; without a
+///   KYC cap or a freeze no account is pending or frozen, so `open` fails for an
+///   existing one.
+#[derive(Debug, Default)]
+struct Model {
+    accounts: BTreeMap<u64, ModelAccount>,
+    deposits: HashMap<u64, (u64, Decimal)>,
+    processed: HashSet<u64>,
+    disputes: HashMap<(u64, u64), Decimal>,
+    /// Deposits less withdrawals, chargebacks and payouts: what the accounts should add
+    /// up to.
+    funds: Decimal,
+}
+
+impl Model {
+    fn apply(&mut self, transaction: &Transaction) -> bool {
+        let client = transaction.client().0;
+        let tx = transaction.tx().0;
+        let Some(account) = self.accounts.get(&client).cloned() else {
+            return match transaction {
+                Transaction::Open(_) => {
+                    self.accounts.insert(client, ModelAccount::default());
+                    true
+                }
+                Transaction::Deposit(_) | Transaction::Withdrawal(_) => {
+                    self.apply_to(client, tx, ModelAccount::default(), transaction)
+                }
+                _ => false,
+            };
+        };
+        if account.locked || account.closed {
+            return false;
+        }
+        self.apply_to(client, tx, account, transaction)
+    }
+
+    /// Applies the transaction to a copy of the account and keeps it only if the
+    /// transaction is accepted.
+    fn apply_to(
+        &mut self,
+        client: u64,
+        tx: u64,
+        mut account: ModelAccount,
+        transaction: &Transaction,
+    ) -> bool {
+        let accepted = match transaction {
+            Transaction::Dispute(_) => match self.deposits.get(&tx) {
+                Some(&(owner, amount))
+                    if owner == client && !self.disputes.contains_key(&(client, tx)) =>
+                {
+                    account.available -= amount;
+                    account.held += amount;
+                    self.disputes.insert((client, tx), amount);
+                    true
+                }
+                _ => false,
+            },
+            Transaction::Resolve(_) => match self.disputes.remove(&(client, tx)) {
+                Some(amount) => {
+                    account.available += amount;
+                    account.held -= amount;
+                    true
+                }
+                None => false,
+            },
+            Transaction::Chargeback(_) => match self.disputes.remove(&(client, tx)) {
+                Some(amount) => {
+                    account.held -= amount;
+                    account.locked = true;
+                    self.funds -= amount;
+                    true
+                }
+                None => false,
+            },
+            Transaction::Open(_) => false,
+            Transaction::Close(_) if self.processed.contains(&tx) => false,
+            Transaction::Deposit(deposit) => {
+                account.available += deposit.amount;
+                self.deposits.insert(tx, (client, deposit.amount));
+                self.funds += deposit.amount;
+                true
+            }
+            Transaction::Withdrawal(withdrawal) => {
+                let covered = withdrawal.amount <= account.total;
+                if covered {
+                    account.available -= withdrawal.amount;
+                    self.deposits.remove(&tx);
+                    self.funds -= withdrawal.amount;
+                }
+                covered
+            }
+            Transaction::Close(_) => {
+                let closable = account.held.is_zero() && account.available >= Decimal::ZERO;
+                if closable {
+                    self.funds -= account.available;
+                    account.available = Decimal::ZERO;
+                    account.closed = true;
+                }
+                closable
+            }
+        };
+        if accepted {
+            if !matches!(
+                transaction,
+                Transaction::Dispute(_) | Transaction::Resolve(_) | Transaction::Chargeback(_)
+            ) {
+                self.processed.insert(tx);
+            }
+            account.total = account.available + account.held;
+            self.accounts.insert(client, account);
+        }
+        accepted
+    }
+}
+
+fn snapshot(ledger: &Ledger) -> BTreeMap<u64, ModelAccount> {
+    ledger
+        .accounts()
+        .iter()
+        .map(|account| {
+            (
+                account.client().0,
+                ModelAccount {
+                    available: account.available(),
+                    held: account.held(),
+                    total: account.total(),
+                    locked: account.locked(),
+                    closed: account.status() == AccountStatus::Closed,
+                },
+            )
+        })
+        .collect()
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(512))]
+
+    #[test]
+    fn test_money_is_conserved(transactions in transactions()) {
+        // given ...
+        let mut ledger = Ledger::default();
+        let mut model = Model::default();
+
+        // when ...
+        for transaction in &transactions {
+            let _ = ledger.process(transaction);
+            model.apply(transaction);
+        }
+
+        // then ...
+        // The specification lets a dispute take available funds below zero when the
+        // deposit was already spent, and a chargeback lock an account with other
+        // disputes still open. Those are only accepted where the model agrees.
+        let balances: Decimal = ledger.accounts().iter().map(|account| account.total()).sum();
+        prop_assert_eq!(balances, model.funds);
+        if let Err(report) = ledger.verify() {
+            for violation in report.violations() {
+                let expected = match violation {
+                    Violation::NegativeAvailable { client, .. } => model.accounts[&client.0].available < Decimal::ZERO,
+                    Violation::LockedWithOpenDisputes { client, .. } => {
+                        model.accounts[&client.0].locked && !model.accounts[&client.0].held.is_zero()
+                    }
+                    _ => false,
+                };
+                prop_assert!(expected, "{}", report);
+            }
+        }
+    }
+
+    #[test]
+    fn test_ledger_matches_reference_model(transactions in transactions()) {
+        // given ...
+        let mut ledger = Ledger::default();
+        let mut model = Model::default();
+
+        // when ...
+        for transaction in &transactions {
+            let accepted = ledger.process(transaction).is_ok();
+
+            // then ...
+            prop_assert_eq!(accepted, model.apply(transaction), "{:?}", transaction);
+        }
+        prop_assert_eq!(snapshot(&ledger), model.accounts);
+    }
+}
@@ -20,13 +20,13 @@ fn test_clean_resolve() {
     let mut output_reader = ChannelByteReader::new(rx);
 
     // when ...
-    LedgerSystem::new(Ledger::default(), input, output).run();
+    LedgerSystem::new(Ledger::default(), input, output).run().unwrap();
 
     // then ...
     assert_eq!(
         output_reader.read_to_string().unwrap(),
-        "client,available,held,total,locked\n\
-        1,100.0,0.0,100.0,false\n"
+        "client,available,held,total,locked,status,lock_reason\n\
+        1,100.0,0.0,100.0,false,active,\n"
     );
     TEST_LOGS.with_borrow(|logs| {
         assert_eq!(*logs, Vec::<String>::new());
@@ -48,13 +48,13 @@ fn test_resolve_on_locked_account() {
     let mut output_reader = ChannelByteReader::new(rx);
 
     // when ...
-    LedgerSystem::new(Ledger::default(), input, output).run();
+    LedgerSystem::new(Ledger::default(), input, output).run().unwrap();
 
     // then ...
     assert_eq!(
         output_reader.read_to_string().unwrap(),
-        "client,available,held,total,locked\n\
-        1,0.0,0.0,0.0,true\n"
+        "client,available,held,total,locked,status,lock_reason\n\
+        1,0.0,0.0,0.0,true,locked,\n"
     );
     TEST_LOGS.with_borrow(|logs| {
         assert_eq!(*logs, vec![String::from("Account (1) is locked")]);
@@ -74,13 +74,13 @@ fn test_resolve_on_a_non_existent_dispute() {
     let mut output_reader = ChannelByteReader::new(rx);
 
     // when ...
-    LedgerSystem::new(Ledger::default(), input, output).run();
+    LedgerSystem::new(Ledger::default(), input, output).run().unwrap();
 
     // then ...
     assert_eq!(
         output_reader.read_to_string().unwrap(),
-        "client,available,held,total,locked\n\
-        1,100.0,0,100.0,false\n"
+        "client,available,held,total,locked,status,lock_reason\n\
+        1,100.0,0,100.0,false,active,\n"
     );
     TEST_LOGS.with_borrow(|logs| {
         assert_eq!(
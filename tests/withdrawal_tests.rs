@@ -19,13 +19,13 @@ fn test_clean_withdrawals() {
     let mut output_reader = ChannelByteReader::new(rx);
 
     // when ...
-    LedgerSystem::new(Ledger::default(), input, output).run();
+    LedgerSystem::new(Ledger::default(), input, output).run().unwrap();
 
     // then ...
     assert_eq!(
         output_reader.read_to_string().unwrap(),
-        "client,available,held,total,locked\n\
-        1,50.0,0,50.0,false\n"
+        "client,available,held,total,locked,status,lock_reason\n\
+        1,50.0,0,50.0,false,active,\n"
     );
     TEST_LOGS.with_borrow(|logs| {
         assert_eq!(*logs, Vec::<String>::new());
@@ -44,13 +44,13 @@ fn test_withdrawal_on_an_empty_account() {
     let mut output_reader = ChannelByteReader::new(rx);
 
     // when ...
-    LedgerSystem::new(Ledger::default(), input, output).run();
+    LedgerSystem::new(Ledger::default(), input, output).run().unwrap();
 
     // then ...
+    // The rejected withdrawal does not open an account.
     assert_eq!(
         output_reader.read_to_string().unwrap(),
-        "client,available,held,total,locked\n\
-        1,0,0,0,false\n"
+        "client,available,held,total,locked,status,lock_reason\n"
     );
     TEST_LOGS.with_borrow(|logs| {
         assert_eq!(
@@ -75,13 +75,13 @@ fn test_withdrawal_on_a_locked_account() {
     let mut output_reader = ChannelByteReader::new(rx);
 
     // when ...
-    LedgerSystem::new(Ledger::default(), input, output).run();
+    LedgerSystem::new(Ledger::default(), input, output).run().unwrap();
 
     // then ...
     assert_eq!(
         output_reader.read_to_string().unwrap(),
-        "client,available,held,total,locked\n\
-        1,0.0,0.0,0.0,true\n"
+        "client,available,held,total,locked,status,lock_reason\n\
+        1,0.0,0.0,0.0,true,locked,\n"
     );
     TEST_LOGS.with_borrow(|logs| {
         assert_eq!(*logs, vec![String::from("Account (1) is locked")]);